@@ -0,0 +1,175 @@
+use bevy::{ecs::{resource::Resource, system::{Query, Res}}, math::{Vec2, Vec3}, transform::components::Transform};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::Velocity;
+
+/// Particles closer than this to a soft wall are pushed back
+pub const SOFT_WALL_MARGIN: f32 = 50.0;
+
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BoundsMode{
+    /// Particles can fly anywhere
+    None,
+    /// Particles leaving on one side come back on the opposite side
+    Wrap,
+    /// Particles bounce off the edges
+    Reflect,
+    /// Particles are pushed back when they get close to the edges
+    SoftWalls,
+}
+
+/// The rectangle particles live in, centered on the origin
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct WorldBounds{
+    pub mode: BoundsMode,
+    pub size: Vec2,
+}
+
+impl Default for WorldBounds{
+    fn default() -> Self {
+        Self { mode: BoundsMode::None, size: Vec2::new(1280.0, 720.0) }
+    }
+}
+
+impl WorldBounds{
+    fn half_size(&self) -> Vec2{
+        self.size / 2.0
+    }
+
+    /// Where else to look for the neighbours of `position` within `radius`. With wrap-around,
+    /// particles near an edge also see the ones near the opposite edge, as if the world repeated
+    /// itself. Neighbours found at `position + shift` are at `their position - shift` from the
+    /// point of view of the particle. The world has to be larger than twice the radius, which
+    /// `check` makes sure of, otherwise the same neighbour would be found more than once.
+    pub fn wrap_shifts(&self, position: Vec2, radius: f32) -> impl Iterator<Item = Vec2>{
+        let half_size = self.half_size();
+        let wrap = self.mode == BoundsMode::Wrap;
+        let shifts = move |position: f32, half_size: f32, size: f32|{
+            let before = wrap && position - radius < -half_size;
+            let after = wrap && position + radius > half_size;
+            [Some(0.0), before.then_some(size), after.then_some(-size)].into_iter().flatten()
+        };
+
+        let xs = shifts(position.x, half_size.x, self.size.x);
+        shifts(position.y, half_size.y, self.size.y).flat_map(move |y| xs.clone().map(move |x| Vec2::new(x, y)))
+    }
+
+    /// Makes sure that particles seeing up to `perception_radius` far can live in this world
+    pub fn check(&self, perception_radius: f32) -> Result<(), String>{
+        if !self.size.is_finite() || self.size.min_element() <= 0.0{
+            return Err(format!("The world can't be {}x{} large", self.size.x, self.size.y));
+        }
+        if self.mode == BoundsMode::Wrap && self.size.min_element() <= 2.0 * perception_radius{
+            return Err(format!(
+                "With wrap-around, the world has to be larger than twice the perception radius of {perception_radius} in both directions, but it is {}x{} large",
+                self.size.x, self.size.y,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Brings a particle that left the world back in, depending on the mode
+    pub fn confine(&self, position: &mut Vec3, velocity: &mut Vec3){
+        let half_size = self.half_size();
+        for axis in 0..2{
+            let (min, max) = (-half_size[axis], half_size[axis]);
+            match self.mode{
+                BoundsMode::Wrap => position[axis] = (position[axis] - min).rem_euclid(max - min) + min,
+                BoundsMode::Reflect if position[axis] < min => {
+                    position[axis] = 2.0 * min - position[axis];
+                    velocity[axis] = velocity[axis].abs();
+                },
+                BoundsMode::Reflect if position[axis] > max => {
+                    position[axis] = 2.0 * max - position[axis];
+                    velocity[axis] = -velocity[axis].abs();
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Pushes particles near a soft wall back into the world. The push grows from nothing at
+    /// `SOFT_WALL_MARGIN` away from the wall to one at the wall and keeps growing beyond it.
+    pub fn wall_force(&self, position: Vec2) -> Vec2{
+        if self.mode != BoundsMode::SoftWalls{
+            return Vec2::ZERO;
+        }
+        let inner = self.half_size() - SOFT_WALL_MARGIN;
+        let outside = (position.abs() - inner).max(Vec2::ZERO);
+        -position.signum() * outside / SOFT_WALL_MARGIN
+    }
+}
+
+pub fn confine_to_bounds(
+    bounds: Res<WorldBounds>,
+    particles: Query<(&mut Transform, &mut Velocity)>,
+){
+    if matches!(bounds.mode, BoundsMode::None | BoundsMode::SoftWalls){
+        return;
+    }
+    for (mut transform, mut velocity) in particles{
+        bounds.confine(&mut transform.translation, &mut velocity.0);
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use bevy::math::{Vec2, Vec3};
+
+    use crate::bounds::{BoundsMode, WorldBounds};
+
+    fn bounds(mode: BoundsMode) -> WorldBounds{
+        WorldBounds { mode, size: Vec2::new(200.0, 100.0) }
+    }
+
+    #[test]
+    fn wrap_shifts_near_edges(){
+        let shifts = |bounds: WorldBounds, position: Vec2| bounds.wrap_shifts(position, 10.0).collect::<Vec<_>>();
+
+        assert_eq!(shifts(bounds(BoundsMode::Wrap), Vec2::ZERO), [Vec2::ZERO]);
+        assert_eq!(shifts(bounds(BoundsMode::Wrap), Vec2::new(95.0, 0.0)), [Vec2::ZERO, Vec2::new(-200.0, 0.0)]);
+        assert_eq!(shifts(bounds(BoundsMode::Wrap), Vec2::new(-95.0, 45.0)), [
+            Vec2::ZERO, Vec2::new(200.0, 0.0),
+            Vec2::new(0.0, -100.0), Vec2::new(200.0, -100.0),
+        ]);
+        // Only wrap-around lets particles see across the edges
+        assert_eq!(shifts(bounds(BoundsMode::Reflect), Vec2::new(95.0, 0.0)), [Vec2::ZERO]);
+    }
+
+    #[test]
+    fn world_has_to_fit_the_perception_radius(){
+        assert!(bounds(BoundsMode::Wrap).check(49.0).is_ok());
+        assert!(bounds(BoundsMode::Wrap).check(50.0).is_err());
+        // Without wrap-around, particles don't see across the edges
+        assert!(bounds(BoundsMode::Reflect).check(50.0).is_ok());
+
+        let empty = WorldBounds{ mode: BoundsMode::None, size: Vec2::new(0.0, 100.0) };
+        assert_eq!(empty.check(10.0), Err("The world can't be 0x100 large".to_owned()));
+        assert!(WorldBounds{ size: Vec2::new(f32::NAN, 100.0), ..empty }.check(10.0).is_err());
+    }
+
+    #[test]
+    fn confine_particles(){
+        let confined = |mode: BoundsMode, position: Vec3, velocity: Vec3|{
+            let (mut position, mut velocity) = (position, velocity);
+            bounds(mode).confine(&mut position, &mut velocity);
+            (position, velocity)
+        };
+
+        assert_eq!(confined(BoundsMode::Wrap, Vec3::new(105.0, -60.0, 1.0), Vec3::X), (Vec3::new(-95.0, 40.0, 1.0), Vec3::X));
+        assert_eq!(confined(BoundsMode::Reflect, Vec3::new(105.0, -60.0, 1.0), Vec3::new(1.0, -1.0, 0.0)), (Vec3::new(95.0, -40.0, 1.0), Vec3::new(-1.0, 1.0, 0.0)));
+        assert_eq!(confined(BoundsMode::Reflect, Vec3::new(50.0, 0.0, 1.0), Vec3::X), (Vec3::new(50.0, 0.0, 1.0), Vec3::X));
+        assert_eq!(confined(BoundsMode::None, Vec3::new(105.0, 0.0, 1.0), Vec3::X), (Vec3::new(105.0, 0.0, 1.0), Vec3::X));
+    }
+
+    #[test]
+    fn soft_walls_push_back(){
+        let walls = bounds(BoundsMode::SoftWalls);
+        assert_eq!(walls.wall_force(Vec2::ZERO), Vec2::ZERO);
+        assert_eq!(walls.wall_force(Vec2::new(75.0, 0.0)), Vec2::new(-0.5, 0.0));
+        assert_eq!(walls.wall_force(Vec2::new(-100.0, -50.0)), Vec2::new(1.0, 1.0));
+        assert_eq!(bounds(BoundsMode::Wrap).wall_force(Vec2::new(100.0, 0.0)), Vec2::ZERO);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,221 @@
+use std::{num::ParseFloatError, path::PathBuf, str::FromStr};
+
+use bevy::{ecs::resource::Resource, math::Vec2};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::bounds::{BoundsMode, WorldBounds};
+
+/// Also the size of the world in headless runs, unless it is given explicitly
+pub const DEFAULT_WINDOW_SIZE: Vec2 = Vec2::new(1280.0, 720.0);
+
+#[derive(Parser, Debug)]
+#[command(about = "A boids simulation steered by ParticleScript")]
+pub struct Cli{
+    /// Runs `first.pts` in a window if no command is given
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command{
+    /// Runs the simulation in a window
+    Run{
+        /// The particle script steering the particles
+        #[arg(default_value = "first.pts")]
+        script: PathBuf,
+        #[command(flatten)]
+        settings: SimulationSettings,
+        /// Width of the window
+        #[arg(long, default_value_t = DEFAULT_WINDOW_SIZE.x)]
+        width: f32,
+        /// Height of the window
+        #[arg(long, default_value_t = DEFAULT_WINDOW_SIZE.y)]
+        height: f32,
+    },
+    /// Runs the simulation without a window as fast as possible and prints statistics at the end
+    Headless{
+        /// The particle script steering the particles
+        #[arg(default_value = "first.pts")]
+        script: PathBuf,
+        #[command(flatten)]
+        settings: SimulationSettings,
+        /// The number of simulation steps to run
+        #[arg(long, default_value_t = 1000)]
+        ticks: u64,
+    },
+    /// Checks a particle script for errors without running the simulation
+    Check{
+        script: PathBuf,
+    },
+}
+
+impl Default for Command{
+    fn default() -> Self {
+        Cli::parse_from(["boids", "run"]).command.expect("run is a command")
+    }
+}
+
+/// The options every kind of simulation run takes
+#[derive(Args, Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SimulationSettings{
+    /// The total number of particles, split evenly between the two groups
+    #[arg(long, default_value_t = 72)]
+    pub particles: usize,
+    /// The number of predators hunting the particles, in addition to them
+    #[arg(long, default_value_t = 3)]
+    pub predators: usize,
+    /// What happens to particles the predators catch
+    #[arg(long, value_enum, default_value_t = CaptureMode::Despawn)]
+    pub capture: CaptureMode,
+    /// Simulation steps per second
+    #[arg(long, default_value_t = 60.0, value_parser = positive::<f64>)]
+    pub tick_rate: f64,
+    /// Seed for everything random in the simulation. Random if not given, the seed is printed at startup either way.
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Run the flocking steps on a single thread instead of all cores
+    #[arg(long)]
+    pub sequential: bool,
+    /// What happens at the edges of the world
+    #[arg(long, value_enum, default_value_t = BoundsMode::None)]
+    pub bounds: BoundsMode,
+    /// Width of the world. The width of the window if not given.
+    #[arg(long, value_parser = positive::<f32>)]
+    pub world_width: Option<f32>,
+    /// Height of the world. The height of the window if not given.
+    #[arg(long, value_parser = positive::<f32>)]
+    pub world_height: Option<f32>,
+    /// Record the trajectories of all particles to a .csv or .jsonl file
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+    /// Record every this many ticks
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub record_every: u64,
+    /// Continue from a snapshot instead of spawning new particles.
+    /// The settings stored in the snapshot are used, except for the ones about recording and saving this run.
+    #[arg(long)]
+    pub resume: Option<PathBuf>,
+    /// Where to save snapshots to. Windows save one when F5 is pressed, headless runs at their end.
+    #[arg(long)]
+    pub snapshot: Option<PathBuf>,
+}
+
+/// The settings of a run without any options, so that the defaults are only given to clap
+impl Default for SimulationSettings{
+    fn default() -> Self {
+        let Command::Run { settings, .. } = Command::default() else { unreachable!("The default command is run") };
+        settings
+    }
+}
+
+impl SimulationSettings{
+    /// The settings `stored` in a snapshot, with the options that only concern this run taken from these
+    pub fn resumed_from(&self, stored: &SimulationSettings) -> Self{
+        Self{
+            sequential: self.sequential,
+            record: self.record.clone(),
+            record_every: self.record_every,
+            resume: self.resume.clone(),
+            snapshot: self.snapshot.clone(),
+            ..stored.clone()
+        }
+    }
+
+    /// The bounds of the world, as large as `window` unless the size was given explicitly
+    pub fn world_bounds(&self, window: Vec2) -> WorldBounds{
+        WorldBounds{
+            mode: self.bounds,
+            size: Vec2::new(self.world_width.unwrap_or(window.x), self.world_height.unwrap_or(window.y)),
+        }
+    }
+}
+
+/// Parses a finite number greater than zero, for sizes and rates
+fn positive<T>(value: &str) -> Result<T, String>
+where T: FromStr<Err = ParseFloatError> + Into<f64> + Copy{
+    let number = value.parse::<T>().map_err(|error| error.to_string())?;
+    let float: f64 = number.into();
+    if float.is_finite() && float > 0.0{
+        Ok(number)
+    }else{
+        Err(format!("{value} is not a finite number greater than zero"))
+    }
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaptureMode{
+    /// Caught particles are removed
+    Despawn,
+    /// Caught particles become predators themselves
+    Convert,
+}
+
+#[cfg(test)]
+mod test{
+    use std::path::PathBuf;
+
+    use bevy::math::Vec2;
+    use clap::{CommandFactory, Parser};
+
+    use crate::{bounds::{BoundsMode, WorldBounds}, cli::{CaptureMode, Cli, Command, SimulationSettings}};
+
+    #[test]
+    fn cli_is_valid(){
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parse_run(){
+        let cli = Cli::try_parse_from(["boids", "run", "flock.pts", "--particles", "100", "--seed", "7", "--width", "800", "--capture", "convert", "--bounds", "soft-walls", "--world-height", "500"]).unwrap();
+        let Some(Command::Run { script, settings, width, height }) = cli.command else { panic!("Command is not run") };
+
+        assert_eq!(script, PathBuf::from("flock.pts"));
+        assert_eq!(settings, SimulationSettings{
+            particles: 100,
+            capture: CaptureMode::Convert,
+            seed: Some(7),
+            bounds: BoundsMode::SoftWalls,
+            world_height: Some(500.0),
+            ..Default::default()
+        });
+        assert_eq!((width, height), (800.0, 720.0));
+        // The world is as wide as the window, but not as high
+        assert_eq!(settings.world_bounds(Vec2::new(width, height)), WorldBounds{ mode: BoundsMode::SoftWalls, size: Vec2::new(800.0, 500.0) });
+    }
+
+    #[test]
+    fn parse_headless(){
+        let cli = Cli::try_parse_from(["boids", "headless", "--ticks", "500", "--bounds", "wrap"]).unwrap();
+        let Some(Command::Headless { script, settings, ticks }) = cli.command else { panic!("Command is not headless") };
+
+        assert_eq!(script, PathBuf::from("first.pts"));
+        assert_eq!(ticks, 500);
+        assert_eq!(settings.bounds, BoundsMode::Wrap);
+        // Headless runs don't have a window
+        assert!(Cli::try_parse_from(["boids", "headless", "--width", "800"]).is_err());
+    }
+
+    #[test]
+    fn rates_and_sizes_have_to_be_positive(){
+        for arguments in [["--tick-rate", "0"], ["--tick-rate=-5", "--sequential"], ["--tick-rate", "inf"], ["--tick-rate", "NaN"], ["--world-width", "0"], ["--world-height=-720", "--sequential"]]{
+            assert!(Cli::try_parse_from(["boids", "headless"].into_iter().chain(arguments)).is_err(), "{arguments:?} was accepted");
+        }
+
+        let cli = Cli::try_parse_from(["boids", "headless", "--tick-rate", "0.5", "--world-width", "1e3"]).unwrap();
+        let Some(Command::Headless { settings, .. }) = cli.command else { panic!("Command is not headless") };
+        assert_eq!((settings.tick_rate, settings.world_width), (0.5, Some(1000.0)));
+    }
+
+    #[test]
+    fn defaults_to_running_first_script(){
+        let Command::Run { script, settings, .. } = Command::default() else { panic!("Default command is not run") };
+        assert_eq!(script, PathBuf::from("first.pts"));
+        assert_eq!(settings, SimulationSettings::default());
+        assert_eq!((settings.particles, settings.predators, settings.tick_rate, settings.record_every), (72, 3, 60.0, 10));
+        assert_eq!((settings.seed, settings.bounds, settings.sequential), (None, BoundsMode::None, false));
+
+        assert!(Cli::try_parse_from(["boids", "check"]).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,130 @@
+use std::{fmt::Display, time::{Duration, Instant}};
+
+use bevy::{app::App, ecs::{query::With, world::World}, time::{Fixed, Time, TimeUpdateStrategy, Virtual}};
+
+use crate::{predation::Captures, species::Species, Particle, Velocity};
+
+/// Runs `ticks` steps of the simulation in `app` one after the other, without waiting for the
+/// time to pass in between. Every update of the app advances the time by exactly one tick, like in a window.
+pub fn simulate(app: &mut App, ticks: u64) -> Statistics{
+    app.finish();
+    app.cleanup();
+
+    let timestep = app.world().resource::<Time<Fixed>>().timestep();
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(timestep));
+    // Slow tick rates would otherwise be clamped and skip their ticks
+    let mut virtual_time = app.world_mut().resource_mut::<Time<Virtual>>();
+    let max_delta = virtual_time.max_delta().max(timestep);
+    virtual_time.set_max_delta(max_delta);
+
+    // The first update runs the startup systems. The clock only starts then, so no tick runs yet.
+    app.update();
+
+    let start = Instant::now();
+    for _ in 0..ticks{
+        app.update();
+    }
+    let elapsed = start.elapsed();
+
+    Statistics::collect(app.world_mut(), ticks, elapsed)
+}
+
+/// How a species is doing at the end of a run
+#[derive(Debug, PartialEq)]
+pub struct SpeciesStatistics{
+    pub species: Species,
+    pub count: usize,
+    pub mean_speed: f32,
+    pub caught: usize,
+}
+
+/// What happened in a headless run
+#[derive(Debug)]
+pub struct Statistics{
+    pub ticks: u64,
+    pub elapsed: Duration,
+    pub species: Vec<SpeciesStatistics>,
+}
+
+impl Statistics{
+    fn collect(world: &mut World, ticks: u64, elapsed: Duration) -> Self{
+        let mut particles = world.query_filtered::<(&Species, &Velocity), With<Particle>>();
+        let captures = world.resource::<Captures>();
+
+        let species = Species::ALL.map(|species|{
+            let speeds = particles.iter(world).filter(|(s, _)| **s == species).map(|(_, velocity)| velocity.0.length()).collect::<Vec<_>>();
+            SpeciesStatistics{
+                species,
+                count: speeds.len(),
+                // Species that died out don't have a speed
+                mean_speed: if speeds.is_empty() { 0.0 } else { speeds.iter().sum::<f32>() / speeds.len() as f32 },
+                caught: captures.caught(species),
+            }
+        });
+        Self { ticks, elapsed, species: species.into() }
+    }
+}
+
+impl Display for Statistics{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ticks_per_second = self.ticks as f64 / self.elapsed.as_secs_f64();
+        write!(f, "Simulated {} ticks in {:.3?} ({ticks_per_second:.1} ticks per second)", self.ticks, self.elapsed)?;
+        for species in &self.species{
+            write!(f, "\n{:>8}: {:>5} particles, mean speed {:>6.1}, {} caught", species.species.name(), species.count, species.mean_speed, species.caught)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use bevy::time::{Fixed, Time};
+
+    use crate::{bounds::BoundsMode, cli::{CaptureMode, SimulationSettings}, headless::simulate, species::Species, testing::test_app, Tick};
+
+    fn settings(capture: CaptureMode) -> SimulationSettings{
+        SimulationSettings{
+            particles: 60,
+            predators: 4,
+            capture,
+            seed: Some(9),
+            bounds: BoundsMode::SoftWalls,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn headless_run_accounts_for_every_particle(){
+        let statistics = simulate(&mut test_app(settings(CaptureMode::Despawn)), 300);
+        assert_eq!(statistics.ticks, 300);
+
+        let species = |s: Species| &statistics.species[s.index()];
+        let survivors = species(Species::Red).count + species(Species::Green).count;
+        let caught = species(Species::Red).caught + species(Species::Green).caught;
+        assert_eq!(survivors + caught, 60);
+        assert_eq!(species(Species::Predator).count, 4);
+        assert!(species(Species::Predator).mean_speed > 0.0);
+
+        let report = statistics.to_string();
+        assert!(report.starts_with("Simulated 300 ticks in "));
+        assert_eq!(report.lines().count(), 1 + Species::ALL.len());
+    }
+
+    #[test]
+    fn every_update_is_one_tick(){
+        for tick_rate in [60.0, 2.0]{
+            let mut app = test_app(SimulationSettings{ tick_rate, ..settings(CaptureMode::Despawn) });
+            simulate(&mut app, 25);
+
+            let time = app.world().resource::<Time<Fixed>>();
+            assert_eq!(time.elapsed(), time.timestep() * 25);
+            assert_eq!(*app.world().resource::<Tick>(), Tick(25));
+        }
+    }
+
+    #[test]
+    fn headless_runs_are_reproducible(){
+        let counts = |capture| simulate(&mut test_app(settings(capture)), 200).species.into_iter().map(|s| (s.count, s.mean_speed.to_bits(), s.caught)).collect::<Vec<_>>();
+        assert_eq!(counts(CaptureMode::Convert), counts(CaptureMode::Convert));
+    }
+}
\ No newline at end of file
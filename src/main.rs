@@ -1,39 +1,186 @@
-use std::{fs::File, io::{BufReader, Read}};
+use std::{fs, io::IsTerminal, path::{Path, PathBuf}, process::ExitCode};
 
 use bevy::prelude::*;
-use bevy::{app::{App, Startup}, asset::Assets, color::Color, core_pipeline::core_2d::Camera2d, ecs::{component::Component, system::{Commands, Query, ResMut}}, math::{primitives::Circle, Vec2, Vec3}, render::mesh::{Mesh, Mesh2d}, sprite::{ColorMaterial, MeshMaterial2d}, transform::components::Transform, DefaultPlugins};
-use itertools::Itertools;
+use bevy::{app::{App, Startup}, ecs::{component::Component, system::{Commands, Query, ResMut}}, math::{Vec2, Vec3}, transform::components::Transform};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
 use rand::Rng;
 
-use crate::particlescript::{lexer::Lexer, parser};
+use crate::{bounds::WorldBounds, cli::{Cli, Command, SimulationSettings, DEFAULT_WINDOW_SIZE}, obstacles::Obstacle, predation::{Capture, Captures, Prey}, recorder::{Recorder, RunHeader}, rng::SimulationRng, script::{ParticleScript, SteeringInput}, snapshot::Snapshot, spatial::{GridEntry, SpatialGrid}, species::{Reaction, Species, SpeciesRules}};
 
+mod bounds;
+mod cli;
+mod headless;
+mod obstacles;
 mod particlescript;
-const TICK_RATE: f32 = 60.0;
-fn main() {
-
-    parse_script();
+mod predation;
+mod recorder;
+mod rng;
+mod script;
+mod snapshot;
+mod spatial;
+mod species;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "gui")]
+mod visuals;
+
+/// Particles closer than this influence each other, unless their `FlockParams` say otherwise
+const PERCEPTION_RADIUS: f32 = 75.0;
+
+fn main() -> ExitCode {
+    match Cli::parse().command.unwrap_or_default(){
+        Command::Run { script, settings, width, height } => run(script, settings, width, height),
+        Command::Headless { script, settings, ticks } => headless(&script, settings, ticks),
+        Command::Check { script } => check(&script),
+    }
+}
 
-    App::new()
-        .add_plugins(DefaultPlugins)
+#[cfg(feature = "gui")]
+fn run(script_path: PathBuf, settings: SimulationSettings, width: f32, height: f32) -> ExitCode{
+    let script = match script::load_script(&script_path){
+        Ok(script) => script,
+        Err(error) => {
+            eprintln!("Failed to read {}: {error}", script_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut app = App::new();
+    app
+        .add_plugins(DefaultPlugins.set(WindowPlugin{
+            primary_window: Some(Window{
+                resolution: (width, height).into(),
+                ..default()
+            }),
+            ..default()
+        }))
         .insert_resource(ClearColor(Color::srgb(0.0, 0.0, 0.0))) // background color
-        .add_systems(Startup, spawn_particles)
-        .insert_resource(Time::<Fixed>::from_hz(TICK_RATE as f64)) 
-        .add_systems(FixedUpdate, (update_particle_data, update_particles, apply_velocity).chain())
-        .run()
+        .insert_resource(script::ScriptWatcher::new(script_path))
+        .add_systems(Update, script::reload_script)
+        .add_systems(Startup, visuals::setup)
+        .add_systems(Update, (visuals::draw_particles, visuals::draw_obstacles, snapshot::save_on_key))
         ;
+    if let Err(error) = add_simulation(&mut app, script, settings, Vec2::new(width, height)){
+        eprintln!("{error}");
+        return ExitCode::FAILURE;
+    }
+    app.run();
+    ExitCode::SUCCESS
+}
+
+#[cfg(not(feature = "gui"))]
+fn run(_script_path: PathBuf, _settings: SimulationSettings, _width: f32, _height: f32) -> ExitCode{
+    eprintln!("This build can't show windows, it was built without the gui feature. Use the headless command instead.");
+    ExitCode::FAILURE
 }
 
-fn parse_script(){
-    let file = File::open("first.pts").expect("Particle script Source file not found");
-    let reader = BufReader::new(file);
-    let mut reader = utf8_read::Reader::new(reader);
-    let mut reader = reader.map(|s| s.expect("Invalid utf8 character in source file"));
+/// Runs the simulation without a window and prints how it went
+fn headless(script_path: &Path, settings: SimulationSettings, ticks: u64) -> ExitCode{
+    // Unlike in a window, the script can't be fixed while the simulation runs
+    let Some(script) = compile(script_path) else { return ExitCode::FAILURE };
+
+    let snapshot_path = settings.snapshot.clone();
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins);
+    if let Err(error) = add_simulation(&mut app, script, settings, DEFAULT_WINDOW_SIZE){
+        eprintln!("{error}");
+        return ExitCode::FAILURE;
+    }
 
-    let lexer: Lexer<&mut std::iter::Map<&mut utf8_read::Reader<BufReader<File>>, _>> = Lexer::new(&mut reader);
+    println!("{}", headless::simulate(&mut app, ticks));
 
-    let mut lexer = lexer.multipeek();
-    let mut scope = parser::Scope::root();
-    let stmt = parser::parse(&mut lexer, &mut scope);
+    if let Some(path) = snapshot_path{
+        if let Err(error) = Snapshot::capture(app.world_mut()).save(&path){
+            eprintln!("Failed to save a snapshot to {}: {error}", path.display());
+            return ExitCode::FAILURE;
+        }
+        println!("Saved a snapshot to {}", path.display());
+    }
+    ExitCode::SUCCESS
+}
+
+/// Everything that moves the particles, no matter whether they are shown in a window.
+/// The world is as large as `window` unless the settings say otherwise, or it is resumed from a snapshot.
+/// Fails if the snapshot to resume from can't be read, the world is too small or the recording can't be created.
+fn add_simulation(app: &mut App, script: ParticleScript, settings: SimulationSettings, window: Vec2) -> Result<(), String>{
+    let (settings, snapshot) = match &settings.resume{
+        Some(path) => {
+            let snapshot = Snapshot::load(path)?;
+            println!("Resuming from {}", path.display());
+            (settings.resumed_from(&snapshot.settings), Some(snapshot))
+        },
+        None => (settings, None),
+    };
+    let bounds = snapshot.as_ref().map_or_else(|| settings.world_bounds(window), |snapshot| snapshot.bounds);
+    // The parameters stored in the snapshot replace the ones of the script
+    let params = snapshot.as_ref().map_or(script.params, |snapshot| snapshot.params);
+    bounds.check(Species::max_perception_radius(params))?;
+
+    let rng = match &snapshot{
+        Some(snapshot) => snapshot.rng.clone(),
+        None => SimulationRng::new(settings.seed),
+    };
+    println!("Seed: {}", rng.seed());
+
+    if let Some(path) = &settings.record{
+        let header = RunHeader{ seed: rng.seed(), settings: &settings, world_size: bounds.size.to_array(), params: &params };
+        let recorder = Recorder::create(path, settings.record_every, &header).map_err(|error| format!("Failed to record to {}: {error}", path.display()))?;
+        app.insert_resource(recorder);
+    }
+
+    app
+        .insert_resource(Time::<Fixed>::from_hz(settings.tick_rate))
+        .insert_resource(params)
+        .insert_non_send_resource(script)
+        .insert_resource(bounds)
+        .insert_resource(rng)
+        .insert_resource(settings)
+        .insert_resource(SpatialGrid::new(PERCEPTION_RADIUS))
+        .insert_resource(SpeciesRules::default())
+        .init_resource::<Captures>()
+        .init_resource::<Tick>()
+        .add_event::<Capture>()
+        .add_systems(FixedUpdate, (
+            count_ticks,
+            rebuild_spatial_grid,
+            update_particle_data,
+            predation::capture_prey,
+            update_particles,
+            apply_velocity,
+            bounds::confine_to_bounds,
+            recorder::record_trajectories.run_if(resource_exists::<Recorder>),
+        ).chain())
+        ;
+
+    match snapshot{
+        // The snapshot brings its own particles and obstacles
+        Some(snapshot) => snapshot.restore(app.world_mut()),
+        None => { app.add_systems(Startup, (spawn_particles, obstacles::spawn_obstacles)); },
+    }
+    Ok(())
+}
+
+/// Reports whether a script compiles and runs without errors
+fn check(script_path: &Path) -> ExitCode{
+    let Some(script) = compile(script_path) else { return ExitCode::FAILURE };
+
+    script.print_variables();
+    println!("{} is fine", script_path.display());
+    ExitCode::SUCCESS
+}
+
+/// Reads and runs a script, printing what went wrong if it can't
+fn compile(script_path: &Path) -> Option<ParticleScript>{
+    let source = match fs::read_to_string(script_path){
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Failed to read {}: {error}", script_path.display());
+            return None;
+        }
+    };
+
+    ParticleScript::new(&source, std::io::stderr().is_terminal()).inspect_err(|error| eprintln!("{error}")).ok()
 }
 
 #[derive(Component, Clone, Copy)]
@@ -44,144 +191,546 @@ struct Particle{
 #[derive(Component)]
 struct Velocity(Vec3);
 
-#[derive(Component, Clone, Copy)]
+/// How many ticks the simulation has run, including the ones before it was resumed
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+struct Tick(u64);
+
+/// How a particle flocks. Every particle has its own, so that groups of particles can behave differently.
+/// As a resource, these are the parameters the script sets for all particles.
+#[derive(Component, Resource, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+struct FlockParams{
+    /// Particles closer than this are neighbours
+    perception_radius: f32,
+    /// Neighbours closer than this are avoided
+    separation_radius: f32,
+    cohesion: f32,
+    alignment: f32,
+    avoidance: f32,
+    /// How strongly particles are pulled towards the center of the world
+    center: f32,
+    /// How strongly particles react to neighbours of other species
+    other_species: f32,
+    /// How strongly hunters go after their prey
+    hunt: f32,
+    /// How strongly particles escape from their hunters
+    flee: f32,
+    /// How strongly particles turn away from obstacles ahead of them
+    obstacle: f32,
+    /// How strongly soft walls push particles back into the world
+    wall: f32,
+    acceleration: f32,
+    max_speed: f32,
+}
+
+impl Default for FlockParams{
+    fn default() -> Self {
+        Self {
+            perception_radius: PERCEPTION_RADIUS,
+            separation_radius: 20.0,
+            cohesion: 0.3,
+            alignment: 1.0,
+            avoidance: 2.0,
+            center: 0.7,
+            other_species: 1.5,
+            hunt: 1.0,
+            flee: 1.5,
+            obstacle: 3.0,
+            wall: 3.0,
+            acceleration: 5.0,
+            max_speed: 100.0,
+        }
+    }
+}
+
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Default)]
 struct ParticleComputationData{
     center: Vec3,
     heading: Vec3,
     avoidance_dir: Vec3,
+    /// Where the reactions to neighbours of other species pull the particle
+    species_dir: Vec3,
+    /// Away from the hunters nearby
+    flee_dir: Vec3,
+    /// The nearest particle this one hunts
+    prey: Option<Prey>,
+    /// Sideways, away from the obstacles ahead
+    obstacle_dir: Vec3,
+    /// Back into the world, near soft walls
+    wall_dir: Vec3,
 }
 
 
-fn rand_vec() -> Vec3{
-    let angle:f32 = rand::rng().random_range(0.0..360.0);
+fn rand_vec(rng: &mut impl Rng) -> Vec3{
+    let angle:f32 = rng.random_range(0.0..360.0);
     let s = angle.sin_cos();
     Vec3::new(s.0, s.1, 0.0)
 }
 
 fn spawn_particles(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut rng: ResMut<SimulationRng>,
+    settings: Res<SimulationSettings>,
+    params: Res<FlockParams>,
 ){
-    commands.spawn(Camera2d);
+    let params = *params;
+    // The first group gets the extra particle of an odd count
+    let mut velocities = (0..settings.particles).map(|_| Velocity(rand_vec(&mut *rng))).collect::<Vec<_>>();
+    let velocities2 = velocities.split_off(settings.particles - settings.particles / 2);
 
 
-    let particle_mesh = meshes.add(Circle::default());
-    let particle_mesh2 = meshes.add(Circle::default());
-    let particle_material = materials.add(Color::srgb(255.0, 0.0, 0.0));
-    let particle_material2 = materials.add(Color::srgb(0.0, 255.0, 0.0));
+    let predator_velocities = (0..settings.predators).map(|_| Velocity(rand_vec(&mut *rng))).collect::<Vec<_>>();
 
     let radius = 75.0;
     let cnt  = 360;
-    let particles = (0..36).map(move |i|{
-        let angle = i as f32 / cnt as f32 * 360.0;
-        let (y, x) = angle.sin_cos();
-        let x = x*radius -100.0;
-        let y = y*radius;
-        (
-            Particle{},
-            Velocity(rand_vec()),
-            ParticleComputationData{
-                center: Vec3::default(),
-                heading: Vec3::default(),
-                avoidance_dir: Vec3::default(),
-            },
-            Mesh2d(particle_mesh.clone()),
-            MeshMaterial2d(particle_material.clone()),
-            Transform::from_translation(Vec3::new(x, y, 1.0)).with_scale(Vec2::splat(5.0).extend(1.))
-        )
+    // Each species starts on its own circle, the reds on the left and the greens on the right
+    let groups = [(Species::Red, velocities, -100.0), (Species::Green, velocities2, 100.0)];
+    let particles = groups.into_iter().flat_map(move |(species, velocities, offset)|{
+        let params = species.flock_params(params);
+
+        velocities.into_iter().enumerate().map(move |(i, velocity)|{
+            let angle = i as f32 / cnt as f32 * 360.0;
+            let (y, x) = angle.sin_cos();
+            let x = x*radius + offset;
+            let y = y*radius;
+            (
+                Particle{},
+                species,
+                velocity,
+                params,
+                ParticleComputationData::default(),
+                Transform::from_translation(Vec3::new(x, y, 1.0)).with_scale(Vec2::splat(5.0).extend(1.))
+            )
+        })
     });
 
-    let particles = particles.chain((0..36).map(move |i|{
-        let angle = i as f32 / cnt as f32 * 360.0;
-        let (y, x) = angle.sin_cos();
-        let x = x*radius + 100.0;
-        let y = y*radius;
+    // The predators start in a row above both groups
+    let predator_params = Species::Predator.flock_params(params);
+    let predator_count = settings.predators;
+    let predators = predator_velocities.into_iter().enumerate().map(move |(i, velocity)|{
+        let x = (i as f32 - (predator_count - 1) as f32 / 2.0) * 30.0;
         (
             Particle{},
-            Velocity(rand_vec()),
-            ParticleComputationData{
-                center: Vec3::default(),
-                heading: Vec3::default(),
-                avoidance_dir: Vec3::default(),
-            },
-            Mesh2d(particle_mesh2.clone()),
-            MeshMaterial2d(particle_material2.clone()),
-            Transform::from_translation(Vec3::new(x, y, 1.0)).with_scale(Vec2::splat(5.0).extend(1.))
+            Species::Predator,
+            velocity,
+            predator_params,
+            ParticleComputationData::default(),
+            Transform::from_translation(Vec3::new(x, 250.0, 1.0)).with_scale(Vec2::splat(7.0).extend(1.))
         )
-    }));
+    });
+
+    commands.spawn_batch(predators);
 
     commands.spawn_batch(particles);
 }
 
+fn count_ticks(mut tick: ResMut<Tick>){
+    tick.0 += 1;
+}
+
 fn apply_velocity(
-    objs: Query<(&Velocity, &mut Transform)>
+    objs: Query<(&Velocity, &mut Transform)>,
+    time: Res<Time>,
 ){
     for (velocity, mut transform) in objs{
-        transform.translation += velocity.0 * TICK_RATE.recip();
+        transform.translation += velocity.0 * time.delta_secs();
     }
 }
 
 
-fn update_particle_data(
-    particles: Query<(&mut ParticleComputationData, &Transform)>,
-    other_particles: Query<(&Particle, &Velocity, &Transform)>
+fn rebuild_spatial_grid(
+    mut grid: ResMut<SpatialGrid>,
+    particles: Query<(Entity, &Species, &Velocity, &Transform), With<Particle>>
 ){
-    for (mut data, transform) in particles{
-
-        let mut count = 0;
-        let mut proximity_count = 0;
-        let mut pos = Vec3::default();
-        let mut heading = Vec3::default();
+    grid.clear();
+    for (entity, species, velocity, transform) in particles{
+        grid.insert(GridEntry { entity, species: *species, position: transform.translation, velocity: velocity.0 });
+    }
+}
 
-        let mut avoidance_dir = Vec3::default();
-        let mut avoidance_count = 0;
-        for (p, velocity, trans) in other_particles {
-            let distance = transform.translation.distance(trans.translation);
+fn update_particle_data(
+    mut particles: Query<(&mut ParticleComputationData, &Transform, &Velocity, &Species, &FlockParams)>,
+    obstacles: Query<&Obstacle>,
+    grid: Res<SpatialGrid>,
+    rules: Res<SpeciesRules>,
+    bounds: Res<WorldBounds>,
+    settings: Res<SimulationSettings>,
+){
+    let update = |(mut data, transform, velocity, species, params): (Mut<ParticleComputationData>, &Transform, &Velocity, &Species, &FlockParams)|{
+        let position = transform.translation.truncate();
+        *data = ParticleComputationData{
+            obstacle_dir: obstacles::avoidance(position, velocity.0.truncate(), obstacles.iter()).extend(0.0),
+            wall_dir: bounds.wall_force(position).extend(0.0),
+            ..particle_data(transform.translation, *species, params, &rules, &bounds, &grid)
+        };
+    };
+
+    // Particles only read the grid, so the order they are updated in doesn't change the results
+    if settings.sequential{
+        particles.iter_mut().for_each(update);
+    }else{
+        particles.par_iter_mut().for_each(update);
+    }
+}
 
-            if distance > 75.0{
-                continue;
-            }
-            pos += trans.translation;
-            count += 1;
+/// Looks at the neighbourhood of a particle
+fn particle_data(position: Vec3, species: Species, params: &FlockParams, rules: &SpeciesRules, bounds: &WorldBounds, grid: &SpatialGrid) -> ParticleComputationData{
+    let mut count = 0;
+    let mut proximity_count = 0;
+    let mut pos = Vec3::default();
+    let mut heading = Vec3::default();
+
+    let mut avoidance_dir = Vec3::default();
+    let mut avoidance_count = 0;
+
+    let mut species_dir = Vec3::default();
+    let mut flee_dir = Vec3::default();
+    let mut prey: Option<Prey> = None;
+    // With wrap-around, neighbours across an edge are seen where they would be if the world repeated itself
+    let neighbours = bounds.wrap_shifts(position.truncate(), params.perception_radius).flat_map(|shift|{
+        grid.neighbours(position.truncate() + shift, params.perception_radius).map(move |other| (other, other.position - shift.extend(0.0)))
+    });
+    for (other, other_position) in neighbours {
+        let distance = position.distance(other_position);
 
-            heading += velocity.0.normalize_or_zero();
-            proximity_count += 1;
+        if distance > params.perception_radius{
+            continue;
+        }
+        let reaction = rules.reaction(species, other.species);
+        match reaction{
+            Reaction::Flock => {
+                pos += other_position;
+                count += 1;
+
+                heading += other.velocity.normalize_or_zero();
+                proximity_count += 1;
+            },
+            Reaction::Avoid => species_dir += (position - other_position).normalize_or_zero(),
+            Reaction::Chase => species_dir += (other_position - position).normalize_or_zero(),
+            Reaction::Flee => flee_dir += (position - other_position).normalize_or_zero(),
+            Reaction::Hunt => if prey.is_none_or(|prey| distance < prey.distance){
+                prey = Some(Prey{ entity: other.entity, position: other_position, distance });
+            },
+        }
 
-            if distance < 20.0{
-                avoidance_dir += (transform.translation - trans.translation).normalize_or_zero();
-                avoidance_count += 1;
-            }
+        // Hunters would never catch their prey if they kept their distance
+        if distance < params.separation_radius && reaction != Reaction::Hunt{
+            avoidance_dir += (position - other_position).normalize_or_zero();
+            avoidance_count += 1;
         }
+    }
 
-        data.center = (pos * (1.0 / count as f32));
-        data.heading = (heading * (proximity_count as f32).recip()).normalize_or_zero();
-        data.avoidance_dir = avoidance_dir * (avoidance_count as f32).recip();
+    ParticleComputationData{
+        center: pos * (1.0 / count as f32),
+        heading: (heading * (proximity_count as f32).recip()).normalize_or_zero(),
+        avoidance_dir: avoidance_dir * (avoidance_count as f32).recip(),
+        species_dir: species_dir.normalize_or_zero(),
+        flee_dir: flee_dir.normalize_or_zero(),
+        prey,
+        obstacle_dir: Vec3::ZERO,
+        wall_dir: Vec3::ZERO,
     }
 }
 
 fn update_particles(
-    particles: Query<(&mut Particle, &mut Velocity, &Transform, &ParticleComputationData)>
+    mut particles: Query<(&mut Velocity, &Transform, &ParticleComputationData, &FlockParams), With<Particle>>,
+    mut script: NonSendMut<ParticleScript>,
+    settings: Res<SimulationSettings>,
 ){
-    let mut avoidance_counter = 0;
-    for (mut p, mut vel, transform, data) in particles{
+    // The interpreter of the script can't leave the main thread
+    if script.has_steering() || settings.sequential{
+        for (mut vel, transform, data, params) in &mut particles{
+
+            // A steering rule declared in the script replaces the builtin one
+            let scripted = script.steer(SteeringInput{
+                position: transform.translation.truncate(),
+                velocity: vel.0.truncate(),
+                center: data.center.truncate(),
+                heading: data.heading.truncate(),
+                avoidance_dir: data.avoidance_dir.truncate(),
+                species_dir: data.species_dir.truncate(),
+                params: *params,
+            });
+
+            let acceleration = scripted.map(|a| a.extend(0.0)).unwrap_or_else(|| builtin_steering(transform, data, params));
+            accelerate(&mut vel, acceleration + additional_forces(transform, data, params), params);
+        }
+    }else{
+        particles.par_iter_mut().for_each(|(mut vel, transform, data, params)|{
+            accelerate(&mut vel, builtin_steering(transform, data, params) + additional_forces(transform, data, params), params);
+        });
+    }
+}
+
+/// The acceleration of a particle if the script doesn't declare a steering rule
+fn builtin_steering(transform: &Transform, data: &ParticleComputationData, params: &FlockParams) -> Vec3{
+    let fixed_center_cohesion = -transform.translation.normalize_or_zero();
 
-        let fixed_center_cohesion = -transform.translation.normalize_or_zero();
+    let cohesion = (data.center - transform.translation).normalize_or_zero();
 
-        let cohesion = (data.center - transform.translation).normalize_or_zero();
+    let avoidance = data.avoidance_dir.normalize_or_zero();
+    let direction = params.cohesion * cohesion + params.alignment * data.heading + params.avoidance * avoidance + params.center * fixed_center_cohesion + params.other_species * data.species_dir;
 
-        let avoidance = data.avoidance_dir.normalize_or_zero();
-        if avoidance.length_squared() == 0.0{
-            avoidance_counter += 1;
+    direction.normalize() * params.acceleration
+}
+
+/// Forces added on top of the steering rule, so that they also work with steering rules from scripts
+fn additional_forces(transform: &Transform, data: &ParticleComputationData, params: &FlockParams) -> Vec3{
+    predation::predation_force(transform, data, params)
+        + params.obstacle * params.acceleration * data.obstacle_dir
+        + params.wall * params.acceleration * data.wall_dir
+}
+
+fn accelerate(vel: &mut Velocity, acceleration: Vec3, params: &FlockParams){
+    vel.0 += acceleration;
+
+    vel.0 = vel.0.clamp_length_max(params.max_speed);
+}
+
+#[cfg(test)]
+mod test{
+    use std::time::Duration;
+
+    use bevy::{ecs::{event::Events, system::RunSystemOnce}, tasks::{ComputeTaskPool, TaskPool}};
+
+    use crate::{bounds::BoundsMode, cli::CaptureMode};
+
+    use super::*;
+
+    fn world(sequential: bool) -> World{
+        ComputeTaskPool::get_or_init(TaskPool::default);
+
+        let mut world = World::new();
+        world.insert_resource(SimulationSettings{
+            particles: 300,
+            seed: Some(5),
+            sequential,
+            ..Default::default()
+        });
+        world.insert_resource(SpatialGrid::new(PERCEPTION_RADIUS));
+        world.insert_resource(SpeciesRules::default());
+        world.insert_resource(WorldBounds::default());
+        world.insert_resource(FlockParams::default());
+        world.init_resource::<Captures>();
+        world.init_resource::<Events<Capture>>();
+        world.insert_non_send_resource(ParticleScript::default());
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f64(60.0_f64.recip()));
+        world.insert_resource(time);
+        world
+    }
+
+    fn particle(position: Vec3, velocity: Vec3, species: Species, params: FlockParams) -> impl Bundle{
+        (
+            Particle{},
+            species,
+            Velocity(velocity),
+            params,
+            ParticleComputationData::default(),
+            Transform::from_translation(position),
+        )
+    }
+
+    fn schedule() -> Schedule{
+        let mut schedule = Schedule::default();
+        schedule.add_systems((rebuild_spatial_grid, update_particle_data, predation::capture_prey, update_particles, apply_velocity, bounds::confine_to_bounds).chain());
+        schedule
+    }
+
+    /// Runs the flocking steps on randomly placed particles and returns where they end up
+    fn simulate(sequential: bool) -> Vec<Vec3>{
+        let mut world = world(sequential);
+
+        let mut rng = SimulationRng::new(Some(5));
+        for i in 0..300{
+            let position = Vec3::new(rng.random_range(-200.0..200.0), rng.random_range(-200.0..200.0), 1.0);
+            let species = Species::ALL[i % Species::ALL.len()];
+            world.spawn(particle(position, rand_vec(&mut rng) * 50.0, species, species.flock_params(FlockParams::default())));
+        }
+
+        let mut schedule = schedule();
+        for _ in 0..100{
+            schedule.run(&mut world);
+        }
+
+        world.query::<&Transform>().iter(&world).map(|transform| transform.translation).collect()
+    }
+
+    /// Spawns the particles like the app does and records where all of them are after every tick
+    fn trajectories(seed: u64) -> Vec<Vec<[u32; 3]>>{
+        let mut world = world(false);
+        world.insert_resource(SimulationRng::new(Some(seed)));
+        world.run_system_once(spawn_particles).unwrap();
+
+        let mut schedule = schedule();
+        (0..50).map(|_|{
+            schedule.run(&mut world);
+            world.query_filtered::<&Transform, With<Particle>>().iter(&world).map(|transform| transform.translation.to_array().map(f32::to_bits)).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn same_seed_same_trajectories(){
+        assert_eq!(trajectories(7), trajectories(7));
+        assert_ne!(trajectories(7), trajectories(8));
+    }
+
+    #[test]
+    fn particles_use_their_own_params(){
+        let mut world = world(false);
+        let slow = world.spawn(particle(Vec3::new(-300.0, 0.0, 1.0), Vec3::X * 50.0, Species::Red, FlockParams{ max_speed: 10.0, ..default() })).id();
+        let short_sighted = world.spawn(particle(Vec3::new(300.0, 0.0, 1.0), Vec3::X * 50.0, Species::Red, FlockParams::default())).id();
+        let far_sighted = world.spawn(particle(Vec3::new(300.0, 100.0, 1.0), Vec3::X * 50.0, Species::Red, FlockParams{ perception_radius: 150.0, ..default() })).id();
+
+        schedule().run(&mut world);
+
+        assert_eq!(world.get::<Velocity>(slow).unwrap().0.length(), 10.0);
+        assert!(world.get::<Velocity>(short_sighted).unwrap().0.length() > 10.0);
+        // Only the far sighted particle sees the other one
+        assert_eq!(world.get::<ParticleComputationData>(short_sighted).unwrap().center, Vec3::new(300.0, 0.0, 1.0));
+        assert_eq!(world.get::<ParticleComputationData>(far_sighted).unwrap().center, Vec3::new(300.0, 50.0, 1.0));
+    }
+
+    #[test]
+    fn species_accelerate_by_their_own_params(){
+        for source in [include_str!("../first.pts"), include_str!("../steering.pts")]{
+            let script = ParticleScript::new(source, false).unwrap();
+            let mut world = world(false);
+            // Far enough apart not to see each other
+            let red = world.spawn(particle(Vec3::new(-300.0, 0.0, 1.0), Vec3::ZERO, Species::Red, Species::Red.flock_params(script.params))).id();
+            let green = world.spawn(particle(Vec3::new(300.0, 0.0, 1.0), Vec3::ZERO, Species::Green, Species::Green.flock_params(script.params))).id();
+            world.insert_non_send_resource(script);
+
+            schedule().run(&mut world);
+
+            assert_eq!(world.get::<Velocity>(red).unwrap().0.length(), 5.0);
+            assert_eq!(world.get::<Velocity>(green).unwrap().0.length(), 6.25);
+        }
+    }
+
+    #[test]
+    fn species_react_to_each_other(){
+        let mut world = world(false);
+        let red = world.spawn(particle(Vec3::new(0.0, 0.0, 1.0), Vec3::Y * 50.0, Species::Red, FlockParams::default())).id();
+        let green = world.spawn(particle(Vec3::new(50.0, 0.0, 1.0), Vec3::Y * 50.0, Species::Green, FlockParams::default())).id();
+        let other_red = world.spawn(particle(Vec3::new(0.0, 40.0, 1.0), Vec3::Y * 50.0, Species::Red, FlockParams::default())).id();
+
+        schedule().run(&mut world);
+
+        // The red flees from the green and only flocks with the other red
+        let data = world.get::<ParticleComputationData>(red).unwrap();
+        assert_eq!(data.species_dir, Vec3::NEG_X);
+        assert_eq!(data.center, Vec3::new(0.0, 20.0, 1.0));
+
+        // The green chases both reds and flocks with nobody but itself
+        let data = world.get::<ParticleComputationData>(green).unwrap();
+        assert_eq!(data.center, Vec3::new(50.0, 0.0, 1.0));
+        assert!(data.species_dir.x < 0.0 && data.species_dir.y > 0.0);
+
+        let data = world.get::<ParticleComputationData>(other_red).unwrap();
+        assert!(data.species_dir.x < 0.0 && data.species_dir.y > 0.0);
+    }
+
+    #[test]
+    fn predators_hunt_the_nearest_prey(){
+        let mut world = world(false);
+        let predator = world.spawn(particle(Vec3::new(0.0, 0.0, 1.0), Vec3::ZERO, Species::Predator, FlockParams::default())).id();
+        let red = world.spawn(particle(Vec3::new(10.0, 0.0, 1.0), Vec3::ZERO, Species::Red, FlockParams::default())).id();
+        let green = world.spawn(particle(Vec3::new(0.0, -50.0, 1.0), Vec3::ZERO, Species::Green, FlockParams::default())).id();
+
+        schedule().run(&mut world);
+
+        let data = world.get::<ParticleComputationData>(predator).unwrap();
+        assert_eq!(data.prey.map(|prey| prey.entity), Some(red));
+        // The predator doesn't keep its distance from the red it hunts
+        assert_eq!(data.avoidance_dir, Vec3::ZERO);
+        assert!(world.get::<Velocity>(predator).unwrap().0.x > 0.0);
+
+        assert_eq!(world.get::<ParticleComputationData>(red).unwrap().flee_dir, Vec3::X);
+        assert_eq!(world.get::<ParticleComputationData>(green).unwrap().flee_dir, Vec3::NEG_Y);
+        assert!(world.get::<Velocity>(green).unwrap().0.y < 0.0);
+    }
+
+    #[test]
+    fn caught_prey_is_despawned_or_converted(){
+        for capture in [CaptureMode::Despawn, CaptureMode::Convert]{
+            let mut world = world(false);
+            world.resource_mut::<SimulationSettings>().capture = capture;
+            // Both predators reach the same red, which can only be caught once
+            world.spawn(particle(Vec3::new(-3.0, 0.0, 1.0), Vec3::ZERO, Species::Predator, FlockParams::default()));
+            world.spawn(particle(Vec3::new(3.0, 0.0, 1.0), Vec3::ZERO, Species::Predator, FlockParams::default()));
+            let red = world.spawn(particle(Vec3::new(0.0, 0.0, 1.0), Vec3::ZERO, Species::Red, FlockParams::default())).id();
+            let green = world.spawn(particle(Vec3::new(0.0, 60.0, 1.0), Vec3::ZERO, Species::Green, FlockParams::default())).id();
+
+            schedule().run(&mut world);
+
+            let captures = world.resource::<Captures>();
+            assert_eq!(Species::ALL.map(|species| captures.caught(species)), [1, 0, 0]);
+            let events = world.resource_mut::<Events<Capture>>().drain().collect::<Vec<_>>();
+            assert_eq!(events.len(), 1);
+            assert_eq!((events[0].prey, events[0].species), (red, Species::Red));
+            assert!(world.get_entity(green).is_ok());
+
+            match capture{
+                CaptureMode::Despawn => assert!(world.get_entity(red).is_err()),
+                CaptureMode::Convert => {
+                    assert_eq!(world.get::<Species>(red), Some(&Species::Predator));
+                    assert_eq!(world.get::<FlockParams>(red), Some(&Species::Predator.flock_params(FlockParams::default())));
+                },
+            }
         }
-        let direction = (0.3* cohesion + 1.0*data.heading + 2.0* avoidance + 0.7 * fixed_center_cohesion);
+    }
+
+    #[test]
+    fn particles_steer_around_obstacles(){
+        let mut world = world(false);
+        world.spawn(Obstacle::Circle { center: Vec2::new(40.0, 5.0), radius: 10.0 });
+        let particle = world.spawn(particle(Vec3::new(0.0, 0.0, 1.0), Vec3::X * 50.0, Species::Red, FlockParams{ center: 0.0, ..default() })).id();
+
+        schedule().run(&mut world);
+
+        assert_eq!(world.get::<ParticleComputationData>(particle).unwrap().obstacle_dir.truncate().normalize(), Vec2::NEG_Y);
+        assert!(world.get::<Velocity>(particle).unwrap().0.y < 0.0);
+    }
+
+    #[test]
+    fn soft_walls_push_by_their_own_weight(){
+        let mut world = world(false);
+        world.insert_resource(WorldBounds{ mode: BoundsMode::SoftWalls, size: Vec2::new(400.0, 300.0) });
+        let pushed = world.spawn(particle(Vec3::new(195.0, -100.0, 1.0), Vec3::Y * 50.0, Species::Red, FlockParams{ center: 0.0, obstacle: 0.0, ..default() })).id();
+        let ignoring = world.spawn(particle(Vec3::new(195.0, 100.0, 1.0), Vec3::Y * 50.0, Species::Red, FlockParams{ center: 0.0, wall: 0.0, ..default() })).id();
 
-        let direction = direction.normalize();
+        schedule().run(&mut world);
+
+        for particle in [pushed, ignoring]{
+            let data = world.get::<ParticleComputationData>(particle).unwrap();
+            assert_eq!(data.obstacle_dir, Vec3::ZERO);
+            assert!(data.wall_dir.x < 0.0);
+        }
+        assert!(world.get::<Velocity>(pushed).unwrap().0.x < world.get::<Velocity>(ignoring).unwrap().0.x);
+    }
+
+    #[test]
+    fn neighbours_wrap_around_the_edges(){
+        let mut world = world(false);
+        world.insert_resource(WorldBounds{ mode: BoundsMode::Wrap, size: Vec2::new(400.0, 300.0) });
+        let left = world.spawn(particle(Vec3::new(-199.5, 0.0, 1.0), Vec3::NEG_X * 50.0, Species::Red, FlockParams::default())).id();
+        let right = world.spawn(particle(Vec3::new(190.0, 0.0, 1.0), Vec3::NEG_X * 50.0, Species::Red, FlockParams::default())).id();
+
+        schedule().run(&mut world);
+
+        // Both see each other 10.5 units away, across the edge
+        assert_eq!(world.get::<ParticleComputationData>(left).unwrap().center, Vec3::new(-204.75, 0.0, 1.0));
+        assert_eq!(world.get::<ParticleComputationData>(right).unwrap().center, Vec3::new(195.25, 0.0, 1.0));
+        // The left one flew out on the left and came back on the right
+        assert!(world.get::<Transform>(left).unwrap().translation.x > 190.0);
+    }
 
-        let acceleration: f32 = 5.0;
-        let max_speed:f32 = 100.0;
-        vel.0 += direction * acceleration;
+    #[test]
+    fn parallel_flocking_matches_sequential(){
+        let sequential = simulate(true);
+        let parallel = simulate(false);
 
-        vel.0 = vel.0.clamp_length_max(max_speed);
+        let bits = |positions: &[Vec3]| positions.iter().map(|p| p.to_array().map(f32::to_bits)).collect::<Vec<_>>();
+        assert_eq!(bits(&parallel), bits(&sequential));
     }
 }
\ No newline at end of file
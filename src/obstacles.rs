@@ -0,0 +1,198 @@
+use bevy::{ecs::{component::Component, system::Commands}, math::Vec2};
+#[cfg(feature = "gui")]
+use bevy::{asset::RenderAssetUsages, math::{primitives::{Circle, Rectangle}, Quat}, render::mesh::{Indices, Mesh, PrimitiveTopology}, transform::components::Transform};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// How far ahead of themselves particles look for obstacles
+pub const LOOK_AHEAD: f32 = 60.0;
+
+/// Something particles can't fly through, in world coordinates
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Obstacle{
+    Circle{
+        center: Vec2,
+        radius: f32,
+    },
+    Segment([Vec2; 2]),
+    /// Only convex polygons are drawn correctly, but particles avoid any polygon
+    Polygon(Vec<Vec2>),
+}
+
+impl Obstacle{
+    /// The straight edges of segments and polygons. Circles don't have any.
+    fn edges(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_{
+        let (points, closed): (&[Vec2], bool) = match self{
+            Obstacle::Circle { .. } => (&[], false),
+            Obstacle::Segment(points) => (points, false),
+            Obstacle::Polygon(points) => (points, true),
+        };
+        let closing_edge = points.last().copied().zip(points.first().copied()).filter(|_| closed);
+        points.iter().copied().tuple_windows().chain(closing_edge)
+    }
+
+    /// The point on the outline of the obstacle that is closest to `point`
+    pub fn closest_point(&self, point: Vec2) -> Vec2{
+        match self{
+            Obstacle::Circle { center, radius } => center + (point - center).normalize_or(Vec2::X) * radius,
+            _ => self.edges()
+                .map(|(a, b)| closest_on_segment(point, a, b))
+                .min_by(|a, b| a.distance_squared(point).total_cmp(&b.distance_squared(point)))
+                .unwrap_or(point),
+        }
+    }
+
+    /// How far a ray from `origin` in the direction of `dir` travels before it hits the obstacle.
+    /// `dir` has to be normalized. Hits further away than `max_distance` are ignored.
+    pub fn ray_distance(&self, origin: Vec2, dir: Vec2, max_distance: f32) -> Option<f32>{
+        let distance = match self{
+            Obstacle::Circle { center, radius } => ray_circle(origin, dir, *center, *radius),
+            _ => self.edges().filter_map(|(a, b)| ray_segment(origin, dir, a, b)).min_by(f32::total_cmp),
+        };
+        distance.filter(|distance| *distance <= max_distance)
+    }
+
+    /// The mesh to draw the obstacle with and where to draw it
+    #[cfg(feature = "gui")]
+    pub fn mesh(&self) -> (Mesh, Transform){
+        match self{
+            Obstacle::Circle { center, radius } => (Circle::new(*radius).into(), Transform::from_translation(center.extend(0.0))),
+            Obstacle::Segment([a, b]) => {
+                let transform = Transform::from_translation(a.midpoint(*b).extend(0.0))
+                    .with_rotation(Quat::from_rotation_z((b - a).to_angle()));
+                (Rectangle::new(a.distance(*b), 3.0).into(), transform)
+            },
+            // A triangle fan, like bevy's own mesh for convex polygons
+            Obstacle::Polygon(points) => {
+                let positions = points.iter().map(|p| [p.x, p.y, 0.0]).collect::<Vec<_>>();
+                let indices = (2..points.len() as u32).flat_map(|i| [0, i - 1, i]).collect();
+                let mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+                    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+                    .with_inserted_indices(Indices::U32(indices));
+                (mesh, Transform::default())
+            },
+        }
+    }
+}
+
+fn closest_on_segment(point: Vec2, a: Vec2, b: Vec2) -> Vec2{
+    let edge = b - a;
+    let t = ((point - a).dot(edge) / edge.length_squared()).clamp(0.0, 1.0);
+    // Segments of length zero make t NaN
+    if t.is_nan() { a } else { a + edge * t }
+}
+
+fn ray_circle(origin: Vec2, dir: Vec2, center: Vec2, radius: f32) -> Option<f32>{
+    let offset = origin - center;
+    let b = offset.dot(dir);
+    let discriminant = b * b - (offset.length_squared() - radius * radius);
+    if discriminant < 0.0{
+        return None;
+    }
+    let root = discriminant.sqrt();
+    // Rays starting inside of the circle hit it right away
+    (-b + root >= 0.0).then(|| (-b - root).max(0.0))
+}
+
+fn ray_segment(origin: Vec2, dir: Vec2, a: Vec2, b: Vec2) -> Option<f32>{
+    let edge = b - a;
+    let denominator = dir.perp_dot(edge);
+    if denominator.abs() < f32::EPSILON{
+        return None;
+    }
+    let offset = a - origin;
+    let t = offset.perp_dot(edge) / denominator;
+    let u = offset.perp_dot(dir) / denominator;
+    (t >= 0.0 && (0.0..=1.0).contains(&u)).then_some(t)
+}
+
+/// Turns a particle that is about to run into an obstacle to the side, the more the closer the obstacle is
+pub fn avoidance<'a>(position: Vec2, velocity: Vec2, obstacles: impl Iterator<Item = &'a Obstacle>) -> Vec2{
+    let Some(heading) = velocity.try_normalize() else { return Vec2::ZERO };
+
+    let mut avoidance = Vec2::ZERO;
+    for obstacle in obstacles{
+        let Some(distance) = obstacle.ray_distance(position, heading, LOOK_AHEAD) else { continue };
+
+        // Only the sideways part of the way out turns the particle. Head-on, it has to pick a side.
+        let away = (position - obstacle.closest_point(position)).normalize_or_zero();
+        let sideways = away.reject_from_normalized(heading).try_normalize().unwrap_or(heading.perp());
+        avoidance += sideways * (1.0 - distance / LOOK_AHEAD);
+    }
+    avoidance
+}
+
+/// The obstacles of the world, placed around the starting positions of the particles
+pub fn default_obstacles() -> Vec<Obstacle>{
+    vec![
+        Obstacle::Circle { center: Vec2::new(-350.0, 120.0), radius: 50.0 },
+        Obstacle::Segment([Vec2::new(250.0, -250.0), Vec2::new(450.0, -150.0)]),
+        Obstacle::Polygon(vec![Vec2::new(-400.0, -200.0), Vec2::new(-250.0, -250.0), Vec2::new(-300.0, -120.0)]),
+        Obstacle::Polygon(vec![Vec2::new(300.0, 150.0), Vec2::new(380.0, 150.0), Vec2::new(380.0, 230.0), Vec2::new(300.0, 230.0)]),
+    ]
+}
+
+pub fn spawn_obstacles(mut commands: Commands){
+    commands.spawn_batch(default_obstacles());
+}
+
+#[cfg(test)]
+mod test{
+    use bevy::math::Vec2;
+
+    use crate::obstacles::{avoidance, Obstacle, LOOK_AHEAD};
+
+    fn square() -> Obstacle{
+        Obstacle::Polygon(vec![Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(10.0, 10.0), Vec2::new(0.0, 10.0)])
+    }
+
+    #[test]
+    fn rays_hit_obstacles(){
+        let circle = Obstacle::Circle { center: Vec2::new(20.0, 0.0), radius: 5.0 };
+        assert_eq!(circle.ray_distance(Vec2::ZERO, Vec2::X, 100.0), Some(15.0));
+        assert_eq!(circle.ray_distance(Vec2::ZERO, Vec2::NEG_X, 100.0), None);
+        assert_eq!(circle.ray_distance(Vec2::ZERO, Vec2::Y, 100.0), None);
+        assert_eq!(circle.ray_distance(Vec2::ZERO, Vec2::X, 10.0), None);
+        assert_eq!(circle.ray_distance(Vec2::new(20.0, 0.0), Vec2::X, 10.0), Some(0.0));
+
+        let segment = Obstacle::Segment([Vec2::new(5.0, -5.0), Vec2::new(5.0, 5.0)]);
+        assert_eq!(segment.ray_distance(Vec2::ZERO, Vec2::X, 100.0), Some(5.0));
+        assert_eq!(segment.ray_distance(Vec2::new(0.0, 6.0), Vec2::X, 100.0), None);
+        assert_eq!(segment.ray_distance(Vec2::ZERO, Vec2::Y, 100.0), None);
+
+        // The closing edge of the polygon counts as well
+        assert_eq!(square().ray_distance(Vec2::new(-5.0, 5.0), Vec2::X, 100.0), Some(5.0));
+        assert_eq!(square().ray_distance(Vec2::new(15.0, 5.0), Vec2::NEG_X, 100.0), Some(5.0));
+    }
+
+    #[test]
+    fn closest_points(){
+        let circle = Obstacle::Circle { center: Vec2::ZERO, radius: 5.0 };
+        assert_eq!(circle.closest_point(Vec2::new(0.0, 10.0)), Vec2::new(0.0, 5.0));
+
+        let segment = Obstacle::Segment([Vec2::ZERO, Vec2::new(10.0, 0.0)]);
+        assert_eq!(segment.closest_point(Vec2::new(4.0, 3.0)), Vec2::new(4.0, 0.0));
+        assert_eq!(segment.closest_point(Vec2::new(-4.0, 3.0)), Vec2::ZERO);
+
+        assert_eq!(square().closest_point(Vec2::new(-3.0, 4.0)), Vec2::new(0.0, 4.0));
+    }
+
+    #[test]
+    fn particles_turn_away_from_obstacles_ahead(){
+        let obstacles = [Obstacle::Circle { center: Vec2::new(30.0, 5.0), radius: 10.0 }];
+
+        // Slightly below the center of the circle, so the way around is below it
+        let turn = avoidance(Vec2::ZERO, Vec2::X * 50.0, obstacles.iter());
+        assert!(turn.y < 0.0 && turn.x.abs() < 1e-6);
+
+        // Flying away from the obstacle or past it doesn't change anything
+        assert_eq!(avoidance(Vec2::ZERO, Vec2::NEG_X, obstacles.iter()), Vec2::ZERO);
+        assert_eq!(avoidance(Vec2::ZERO, Vec2::Y, obstacles.iter()), Vec2::ZERO);
+        assert_eq!(avoidance(Vec2::new(-LOOK_AHEAD, 0.0), Vec2::X, obstacles.iter()), Vec2::ZERO);
+
+        // Head-on, particles still pick a side
+        let turn = avoidance(Vec2::new(0.0, 5.0), Vec2::X, obstacles.iter());
+        assert_eq!(turn.x, 0.0);
+        assert!(turn.y != 0.0);
+    }
+}
\ No newline at end of file
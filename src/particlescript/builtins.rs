@@ -0,0 +1,51 @@
+use std::{cell::OnceCell, rc::Rc};
+
+use crate::particlescript::{parser::{Function, FunctionBody, Scope, Variable}, types::ValueData};
+
+/// The functions every script can call without declaring them
+pub fn builtin_functions(scope: &Scope) -> Vec<Function>{
+    let builtin = |name: &str, parameters: &[(&str, &str)], return_type: &str, body: fn(&[ValueData]) -> Option<ValueData>|{
+        let typ = |name: &str| scope.find_type(name).unwrap_or_else(|| panic!("Base type '{name}' is missing"));
+        Function{
+            name: name.to_owned(),
+            parameters: parameters.iter().map(|(name, typ_name)| Rc::new(Variable{
+                name: name.to_string(),
+                typ: OnceCell::from(typ(typ_name)),
+            })).collect(),
+            return_type: typ(return_type),
+            body: FunctionBody::Builtin(body),
+        }
+    };
+
+    vec![
+        builtin("length", &[("v", "Vec2")], "float", |arguments| match arguments{
+            [ValueData::Vec2(v)] => Some(ValueData::Float(v.length())),
+            _ => None,
+        }),
+        // Zero vectors stay zero instead of becoming NaN
+        builtin("normalize", &[("v", "Vec2")], "Vec2", |arguments| match arguments{
+            [ValueData::Vec2(v)] => Some(ValueData::Vec2(v.normalize_or_zero())),
+            _ => None,
+        }),
+        builtin("dot", &[("a", "Vec2"), ("b", "Vec2")], "float", |arguments| match arguments{
+            [ValueData::Vec2(a), ValueData::Vec2(b)] => Some(ValueData::Float(a.dot(*b))),
+            _ => None,
+        }),
+        builtin("distance", &[("a", "Vec2"), ("b", "Vec2")], "float", |arguments| match arguments{
+            [ValueData::Vec2(a), ValueData::Vec2(b)] => Some(ValueData::Float(a.distance(*b))),
+            _ => None,
+        }),
+        builtin("clamp_length", &[("v", "Vec2"), ("max", "float")], "Vec2", |arguments| match arguments{
+            [ValueData::Vec2(v), ValueData::Float(max)] => Some(ValueData::Vec2(v.clamp_length_max(*max))),
+            _ => None,
+        }),
+        builtin("min", &[("a", "float"), ("b", "float")], "float", |arguments| match arguments{
+            [ValueData::Float(a), ValueData::Float(b)] => Some(ValueData::Float(a.min(*b))),
+            _ => None,
+        }),
+        builtin("max", &[("a", "float"), ("b", "float")], "float", |arguments| match arguments{
+            [ValueData::Float(a), ValueData::Float(b)] => Some(ValueData::Float(a.max(*b))),
+            _ => None,
+        }),
+    ]
+}
\ No newline at end of file
@@ -0,0 +1,121 @@
+use std::fmt::Display;
+
+use crate::particlescript::{lexer::{LexerError, Span}, parser::ParseError, typechecker::TypeError};
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// An error message pointing at a location in the source code.
+/// Displaying it renders the offending line with the location underlined, similar to rustc:
+/// ```text
+/// error: Unknown variable 'c'
+///  --> 2:9
+///   |
+/// 2 | let b = c
+///   |         ^
+/// ```
+pub struct Diagnostic<'a>{
+    pub source: &'a str,
+    pub message: String,
+    /// `None` points at the end of the source
+    pub span: Option<Span>,
+    /// Whether to use ANSI escape codes for colors
+    pub colored: bool,
+}
+
+impl<'a> Diagnostic<'a>{
+    pub fn new(source: &'a str, message: String, span: Option<Span>) -> Self{
+        Self { source, message, span, colored: false }
+    }
+
+    pub fn colored(mut self, colored: bool) -> Self{
+        self.colored = colored;
+        self
+    }
+
+    fn end_of_source(&self) -> Span{
+        let line = self.source.lines().count().max(1);
+        let column = self.source.lines().last().map_or(0, |l| l.chars().count()) + 1;
+        Span { line: line as u32, column: column as u32, length: 1 }
+    }
+}
+
+impl Display for Diagnostic<'_>{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (red, blue, bold, reset) = if self.colored{
+            (RED, BLUE, BOLD, RESET)
+        }else{
+            ("", "", "", "")
+        };
+
+        let span = self.span.unwrap_or_else(|| self.end_of_source());
+        let line = self.source.lines().nth(span.line.saturating_sub(1) as usize).unwrap_or("");
+        let gutter = " ".repeat(span.line.to_string().len());
+
+        writeln!(f, "{red}error{reset}{bold}: {}{reset}", self.message)?;
+        writeln!(f, "{gutter}{blue}-->{reset} {}:{}", span.line, span.column)?;
+        writeln!(f, "{gutter} {blue}|{reset}")?;
+        writeln!(f, "{blue}{} |{reset} {line}", span.line)?;
+        write!(f, "{gutter} {blue}|{reset} {}{red}{}{reset}", " ".repeat(span.column.saturating_sub(1) as usize), "^".repeat(span.length.max(1) as usize))
+    }
+}
+
+/// Errors that can be rendered as a `Diagnostic`
+pub trait SourceError{
+    fn diagnostic<'a>(&self, source: &'a str) -> Diagnostic<'a>;
+}
+
+impl SourceError for LexerError{
+    fn diagnostic<'a>(&self, source: &'a str) -> Diagnostic<'a> {
+        Diagnostic::new(source, self.message.clone(), Some(self.span))
+    }
+}
+
+impl SourceError for ParseError{
+    fn diagnostic<'a>(&self, source: &'a str) -> Diagnostic<'a> {
+        Diagnostic::new(source, self.full_message(), self.span())
+    }
+}
+
+impl SourceError for TypeError{
+    fn diagnostic<'a>(&self, source: &'a str) -> Diagnostic<'a> {
+        Diagnostic::new(source, self.message.clone(), Some(self.span))
+    }
+}
+
+
+#[cfg(test)]
+mod test{
+    use itertools::Itertools;
+
+    use crate::particlescript::{diagnostics::SourceError, lexer::Lexer, parser::{parse_program, Scope}};
+
+    fn parse_error(source: &str) -> String{
+        let error = parse_program(&mut Lexer::new(source.chars()).multipeek(), &mut Scope::root()).unwrap_err();
+        error.diagnostic(source).to_string()
+    }
+
+    #[test]
+    fn underlines_token(){
+        assert_eq!(parse_error("let a = 1\nlet b = abc + 2"), [
+            "error: Unknown variable 'abc'",
+            " --> 2:9",
+            "  |",
+            "2 | let b = abc + 2",
+            "  |         ^^^",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn points_at_end_of_file(){
+        assert_eq!(parse_error("let a = (1 +"), [
+            "error: Unexpected end of file, expected operand",
+            " --> 1:13",
+            "  |",
+            "1 | let a = (1 +",
+            "  |             ^",
+        ].join("\n"));
+    }
+}
@@ -0,0 +1,567 @@
+use std::{fmt::Display, rc::Rc};
+
+use bevy::math::Vec2;
+
+use crate::particlescript::{parser::{BinaryOperator, Function, FunctionBody, Program, Scope, Stmt, Variable}, types::{Type, Value, ValueData}};
+
+#[derive(Debug, Clone)]
+pub struct RuntimeError{
+    pub message: String
+}
+
+impl Display for RuntimeError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Runtime error: {}", self.message)
+    }
+}
+
+/// The runtime counterpart of a `Scope`. Holds the current value of every variable
+/// that has been assigned while executing the statements of that scope.
+pub struct Environment{
+    values: Vec<(Rc<Variable>, Value)>,
+    parent_environment: Option<Box<Environment>>,
+}
+
+impl Environment{
+    pub fn root() -> Self{
+        Self { values: vec![], parent_environment: None }
+    }
+
+    pub fn get(&self, variable: &Rc<Variable>) -> Option<&Value>{
+        self.values.iter().find(|(v, _)| Rc::ptr_eq(v, variable)).map(|(_, value)| value).or_else(||{
+            self.parent_environment.as_ref().and_then(|p| p.get(variable))
+        })
+    }
+
+    /// Assigns a value to a variable. If the variable doesn't live in this environment or any
+    /// of its parents yet, it is declared in this one.
+    fn set(&mut self, variable: Rc<Variable>, value: Value){
+        if let Some(slot) = self.find_slot(&variable){
+            *slot = value;
+        }else{
+            self.values.push((variable, value));
+        }
+    }
+
+    fn find_slot(&mut self, variable: &Rc<Variable>) -> Option<&mut Value>{
+        match self.values.iter_mut().find(|(v, _)| Rc::ptr_eq(v, variable)){
+            Some((_, value)) => Some(value),
+            None => self.parent_environment.as_mut().and_then(|p| p.find_slot(variable)),
+        }
+    }
+
+    /// Looks up the value of a variable by its name, which is how the host reads script results.
+    /// If the variable was shadowed, this is the value of the latest declaration.
+    pub fn find(&self, name: &str) -> Option<&Value>{
+        self.values.iter().rev().find(|(v, _)| v.name == name).map(|(_, value)| value).or_else(||{
+            self.parent_environment.as_ref().and_then(|p| p.find(name))
+        })
+    }
+
+    /// Like `find`, but lets the host change the value
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut Value>{
+        match self.values.iter_mut().rev().find(|(v, _)| v.name == name){
+            Some((_, value)) => Some(value),
+            None => self.parent_environment.as_mut().and_then(|p| p.find_mut(name)),
+        }
+    }
+
+    pub fn variables(&self) -> impl Iterator<Item = (&Rc<Variable>, &Value)>{
+        self.values.iter().map(|(v, value)| (v, value))
+    }
+}
+
+/// Executes parsed statements by walking the syntax tree.
+pub struct Interpreter{
+    pub environment: Environment,
+    types: Vec<Rc<Type>>,
+    /// Set by `return`. Statements aren't executed while this is set,
+    /// until the call of the returning function takes the value.
+    returning: Option<Value>,
+}
+
+impl Interpreter{
+    pub fn new(scope: &Scope) -> Self{
+        Self { environment: Environment::root(), types: scope.types().cloned().collect(), returning: None }
+    }
+
+    fn find_type(&self, name: &str) -> Rc<Type>{
+        self.types.iter().find(|t| t.name == name).cloned().unwrap_or_else(|| panic!("Base type '{name}' is missing"))
+    }
+
+    /// Wraps data into a value of the matching base type
+    pub fn value(&self, data: ValueData) -> Value{
+        Value{
+            typ: self.find_type(data.type_name()),
+            data
+        }
+    }
+
+    fn void(&self) -> Value{
+        self.value(ValueData::Void)
+    }
+
+    pub fn run(&mut self, program: &Program) -> Result<(), RuntimeError>{
+        for stmt in &program.statements{
+            self.execute(stmt)?;
+        }
+        Ok(())
+    }
+
+    /// Calls a function with already evaluated arguments.
+    /// The body runs in a child of the current environment.
+    pub fn call(&mut self, function: &Function, arguments: Vec<Value>) -> Result<Value, RuntimeError>{
+        if arguments.len() != function.parameters.len(){
+            return Err(RuntimeError { message: format!("{} takes {} arguments, got {}", function.name, function.parameters.len(), arguments.len()) });
+        }
+
+        match &function.body{
+            FunctionBody::Builtin(body) => {
+                let arguments = function.parameters.iter().zip(arguments).map(|(parameter, argument)| self.promote_for(parameter, argument).data).collect::<Vec<_>>();
+                body(&arguments).map(|data| self.value(data)).ok_or_else(|| RuntimeError{
+                    message: format!("Invalid arguments for {}", function.name)
+                })
+            },
+            FunctionBody::Script(body) => {
+                let returned = self.in_child_environment(|interpreter|{
+                    for (parameter, argument) in function.parameters.iter().zip(arguments){
+                        let argument = interpreter.promote_for(parameter, argument);
+                        interpreter.environment.set(parameter.clone(), argument);
+                    }
+                    interpreter.execute(body)?;
+                    Ok(interpreter.returning.take())
+                })?;
+
+                match returned{
+                    Some(value) => Ok(value),
+                    None if function.return_type.name == "void" => Ok(self.void()),
+                    None => Err(RuntimeError { message: format!("{} ended without returning a value", function.name) }),
+                }
+            },
+        }
+    }
+
+    /// Ints used where a float is expected are promoted
+    fn promote(&self, value: Value, typ: &Type) -> Value{
+        match value.data{
+            ValueData::Int(v) if typ.name == "float" => self.value(ValueData::Float(v as f32)),
+            _ => value,
+        }
+    }
+
+    fn promote_for(&self, variable: &Variable, value: Value) -> Value{
+        match variable.typ.get(){
+            Some(typ) => self.promote(value, typ),
+            None => value,
+        }
+    }
+
+    /// Runs `f` with the environment replaced by an empty child environment,
+    /// so that variables declared inside of `f` are dropped afterwards.
+    fn in_child_environment<R>(&mut self, f: impl FnOnce(&mut Interpreter) -> R) -> R{
+        let parent = std::mem::replace(&mut self.environment, Environment::root());
+        self.environment.parent_environment = Some(Box::new(parent));
+
+        let result = f(self);
+
+        let parent = self.environment.parent_environment.take().expect("Child environment lost its parent");
+        self.environment = *parent;
+        result
+    }
+
+    pub fn execute(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError>{
+        match stmt{
+            Stmt::Assignment { variable, value, .. } => {
+                let value = self.execute(value)?;
+                let value = self.promote_for(variable, value);
+                self.environment.set(variable.clone(), value);
+                Ok(self.void())
+            },
+            Stmt::Literal(value) => Ok(value.clone()),
+            Stmt::VariableReference(variable) => self.environment.get(variable).cloned().ok_or_else(|| RuntimeError{
+                message: format!("Variable '{}' was used before it was assigned", variable.name)
+            }),
+            Stmt::BinaryOperation { operator, lhs, rhs, .. } => {
+                let lhs = self.execute(lhs)?;
+
+                // && and || only evaluate their right side if it can change the result
+                match (operator, lhs.data){
+                    (BinaryOperator::And, ValueData::Bool(false)) | (BinaryOperator::Or, ValueData::Bool(true)) => return Ok(lhs),
+                    _ => {},
+                }
+
+                let rhs = self.execute(rhs)?;
+                self.binary_operation(*operator, lhs, rhs)
+            },
+            Stmt::Negation { operand, .. } => {
+                let operand = self.execute(operand)?;
+                let data = match operand.data{
+                    ValueData::Int(v) => ValueData::Int(v.checked_neg().ok_or_else(overflow)?),
+                    ValueData::Float(v) => ValueData::Float(-v),
+                    ValueData::Vec2(v) => ValueData::Vec2(-v),
+                    _ => return Err(RuntimeError { message: format!("Cannot negate {}", operand.typ.name) }),
+                };
+                Ok(Value { typ: operand.typ, data })
+            },
+            Stmt::Not { operand, .. } => {
+                let operand = self.execute(operand)?;
+                match operand.data{
+                    ValueData::Bool(v) => Ok(self.value(ValueData::Bool(!v))),
+                    _ => Err(RuntimeError { message: format!("Cannot apply ! to {}", operand.typ.name) }),
+                }
+            },
+            Stmt::Construct { typ, arguments, .. } => {
+                let arguments = arguments.iter().map(|argument| self.execute(argument)).collect::<Result<Vec<_>, _>>()?;
+                match (typ.name.as_str(), arguments.as_slice()){
+                    ("Vec2", [x, y]) => Ok(self.value(ValueData::Vec2(Vec2::new(number(x)?, number(y)?)))),
+                    ("float", [v]) => Ok(self.value(ValueData::Float(number(v)?))),
+                    // Floats are truncated
+                    ("int", [v]) => Ok(self.value(ValueData::Int(number(v)? as i32))),
+                    _ => Err(RuntimeError { message: format!("Invalid arguments for constructor of {}", typ.name) }),
+                }
+            },
+            Stmt::FieldAccess { object, field, .. } => {
+                let object = self.execute(object)?;
+                match (object.data, field.as_str()){
+                    (ValueData::Vec2(v), "x") => Ok(self.value(ValueData::Float(v.x))),
+                    (ValueData::Vec2(v), "y") => Ok(self.value(ValueData::Float(v.y))),
+                    _ => Err(RuntimeError { message: format!("{} has no field '{}'", object.typ.name, field) }),
+                }
+            },
+            Stmt::Block(statements) => {
+                self.in_child_environment(|interpreter|{
+                    for stmt in statements{
+                        interpreter.execute(stmt)?;
+                        if interpreter.returning.is_some(){
+                            break;
+                        }
+                    }
+                    Ok(())
+                })?;
+                Ok(self.void())
+            },
+            Stmt::If { condition, then_branch, else_branch, .. } => {
+                if self.condition(condition)?{
+                    self.execute(then_branch)?;
+                }else if let Some(else_branch) = else_branch{
+                    self.execute(else_branch)?;
+                }
+                Ok(self.void())
+            },
+            Stmt::While { condition, body, .. } => {
+                while self.returning.is_none() && self.condition(condition)?{
+                    self.execute(body)?;
+                }
+                Ok(self.void())
+            },
+            Stmt::For { initializer, condition, step, body, .. } => {
+                self.in_child_environment(|interpreter|{
+                    interpreter.execute(initializer)?;
+                    while interpreter.condition(condition)?{
+                        interpreter.execute(body)?;
+                        if interpreter.returning.is_some(){
+                            break;
+                        }
+                        interpreter.execute(step)?;
+                    }
+                    Ok(())
+                })?;
+                Ok(self.void())
+            },
+            Stmt::FunctionDeclaration { .. } => Ok(self.void()),
+            Stmt::Call { function, arguments, .. } => {
+                let arguments = arguments.iter().map(|argument| self.execute(argument)).collect::<Result<Vec<_>, _>>()?;
+                self.call(function, arguments)
+            },
+            Stmt::Return { value, return_type, .. } => {
+                let value = match value{
+                    Some(value) => self.execute(value)?,
+                    None => self.void(),
+                };
+                self.returning = Some(self.promote(value, return_type));
+                Ok(self.void())
+            },
+        }
+    }
+
+    fn condition(&mut self, condition: &Stmt) -> Result<bool, RuntimeError>{
+        let value = self.execute(condition)?;
+        match value.data{
+            ValueData::Bool(v) => Ok(v),
+            _ => Err(RuntimeError { message: format!("Cannot use {} as a condition", value.typ.name) }),
+        }
+    }
+
+    fn binary_operation(&self, operator: BinaryOperator, lhs: Value, rhs: Value) -> Result<Value, RuntimeError>{
+        let unsupported = || RuntimeError {
+            message: format!("Cannot apply '{}' to {} and {}", operator, lhs.typ.name, rhs.typ.name)
+        };
+
+        // An int used together with a float or a vector is promoted to a float
+        let operands = match (lhs.data, rhs.data){
+            (ValueData::Int(a), b @ (ValueData::Float(_) | ValueData::Vec2(_))) => (ValueData::Float(a as f32), b),
+            (a @ (ValueData::Float(_) | ValueData::Vec2(_)), ValueData::Int(b)) => (a, ValueData::Float(b as f32)),
+            operands => operands,
+        };
+
+        let data = match operands{
+            (ValueData::Int(a), ValueData::Int(b)) => match operator{
+                BinaryOperator::Add => ValueData::Int(a.checked_add(b).ok_or_else(overflow)?),
+                BinaryOperator::Subtract => ValueData::Int(a.checked_sub(b).ok_or_else(overflow)?),
+                BinaryOperator::Multiply => ValueData::Int(a.checked_mul(b).ok_or_else(overflow)?),
+                BinaryOperator::Divide => {
+                    if b == 0{
+                        return Err(RuntimeError { message: "Division by zero".to_owned() });
+                    }
+                    ValueData::Int(a.checked_div(b).ok_or_else(overflow)?)
+                },
+                _ => compare(operator, a, b).ok_or_else(unsupported)?,
+            },
+            (ValueData::Float(a), ValueData::Float(b)) => match operator{
+                BinaryOperator::Add => ValueData::Float(a + b),
+                BinaryOperator::Subtract => ValueData::Float(a - b),
+                BinaryOperator::Multiply => ValueData::Float(a * b),
+                BinaryOperator::Divide => ValueData::Float(a / b),
+                _ => compare(operator, a, b).ok_or_else(unsupported)?,
+            },
+            (ValueData::Vec2(a), ValueData::Vec2(b)) => match operator{
+                BinaryOperator::Add => ValueData::Vec2(a + b),
+                BinaryOperator::Subtract => ValueData::Vec2(a - b),
+                BinaryOperator::Multiply => ValueData::Vec2(a * b),
+                BinaryOperator::Divide => ValueData::Vec2(a / b),
+                BinaryOperator::Equal => ValueData::Bool(a == b),
+                BinaryOperator::NotEqual => ValueData::Bool(a != b),
+                _ => return Err(unsupported()),
+            },
+            (ValueData::Vec2(a), ValueData::Float(b)) => match operator{
+                BinaryOperator::Multiply => ValueData::Vec2(a * b),
+                BinaryOperator::Divide => ValueData::Vec2(a / b),
+                _ => return Err(unsupported()),
+            },
+            (ValueData::Float(a), ValueData::Vec2(b)) if operator == BinaryOperator::Multiply => ValueData::Vec2(a * b),
+            (ValueData::Bool(a), ValueData::Bool(b)) => ValueData::Bool(match operator{
+                BinaryOperator::And => a && b,
+                BinaryOperator::Or => a || b,
+                BinaryOperator::Equal => a == b,
+                BinaryOperator::NotEqual => a != b,
+                _ => return Err(unsupported()),
+            }),
+            _ => return Err(unsupported()),
+        };
+        Ok(self.value(data))
+    }
+}
+
+/// Applies a comparison operator. Returns `None` for operators that don't compare.
+fn compare<T: PartialOrd>(operator: BinaryOperator, a: T, b: T) -> Option<ValueData>{
+    Some(ValueData::Bool(match operator{
+        BinaryOperator::Equal => a == b,
+        BinaryOperator::NotEqual => a != b,
+        BinaryOperator::Less => a < b,
+        BinaryOperator::LessOrEqual => a <= b,
+        BinaryOperator::Greater => a > b,
+        BinaryOperator::GreaterOrEqual => a >= b,
+        _ => return None,
+    }))
+}
+
+/// Converts a numeric value to a float
+fn number(value: &Value) -> Result<f32, RuntimeError>{
+    match value.data{
+        ValueData::Int(v) => Ok(v as f32),
+        ValueData::Float(v) => Ok(v),
+        _ => Err(RuntimeError { message: format!("Expected a number, found {}", value.typ.name) }),
+    }
+}
+
+fn overflow() -> RuntimeError{
+    RuntimeError { message: "Integer overflow".to_owned() }
+}
+
+
+#[cfg(test)]
+mod test{
+    use bevy::math::Vec2;
+    use itertools::Itertools;
+
+    use crate::particlescript::{interpreter::Interpreter, lexer::Lexer, parser::{parse, parse_program, Scope}, types::ValueData};
+
+    fn run(source: &str) -> Interpreter{
+        let mut scope = Scope::root();
+        let program = parse_program(&mut Lexer::new(source.chars()).multipeek(), &mut scope).unwrap();
+        let mut interpreter = Interpreter::new(&scope);
+        interpreter.run(&program).unwrap();
+        interpreter
+    }
+
+    fn variable(interpreter: &Interpreter, name: &str) -> ValueData{
+        interpreter.environment.variables().find(|(v, _)| v.name == name).map(|(_, value)| value.data).expect("Variable was not assigned")
+    }
+
+    #[test]
+    fn assignment_stores_value(){
+        let mut scope = Scope::root();
+        let mut tokens = Lexer::new("let h = 1".chars()).multipeek();
+        let stmt = parse(&mut tokens, &mut scope).unwrap();
+
+        let mut interpreter = Interpreter::new(&scope);
+        interpreter.execute(&stmt).unwrap();
+
+        let Some((_, value)) = interpreter.environment.variables().find(|(v, _)| v.name == "h") else { panic!("Variable was not assigned") };
+        assert_eq!(value.data, ValueData::Int(1));
+    }
+
+    #[test]
+    fn arithmetic(){
+        let interpreter = run("let base = 2\nlet speed = (base + 2) * 3 / 4\nlet a = 1 + 2 * 3 - 4\nlet b = 10 - 2 - 3\nlet c = base -1\nlet d = -(base * 3)");
+
+        assert_eq!(variable(&interpreter, "speed"), ValueData::Int(3));
+        assert_eq!(variable(&interpreter, "a"), ValueData::Int(3));
+        assert_eq!(variable(&interpreter, "b"), ValueData::Int(5));
+        assert_eq!(variable(&interpreter, "c"), ValueData::Int(1));
+        assert_eq!(variable(&interpreter, "d"), ValueData::Int(-6));
+    }
+
+    #[test]
+    fn int_limits(){
+        let interpreter = run("let min = -2147483648\nlet max = 2147483647\nlet negated = - -2147483647");
+
+        assert_eq!(variable(&interpreter, "min"), ValueData::Int(i32::MIN));
+        assert_eq!(variable(&interpreter, "max"), ValueData::Int(i32::MAX));
+        assert_eq!(variable(&interpreter, "negated"), ValueData::Int(i32::MAX));
+    }
+
+    #[test]
+    fn division_by_zero(){
+        let mut scope = Scope::root();
+        let program = parse_program(&mut Lexer::new("let a = 1 / (2 - 2)".chars()).multipeek(), &mut scope).unwrap();
+        assert!(Interpreter::new(&scope).run(&program).is_err());
+    }
+
+    #[test]
+    fn control_flow(){
+        let interpreter = run("
+            let sum = 0
+            let i = 5
+            while i > 0 {
+                sum = sum + i
+                i = i - 1
+            }
+
+            let product = 1
+            for (let j = 1; j < 5; j = j + 1) {
+                product = product * j
+            }
+
+            let branch = 0
+            if sum != 15 {
+                branch = 1
+            } else if product != 24 {
+                branch = 2
+            } else {
+                branch = 3
+            }
+        ");
+
+        assert_eq!(variable(&interpreter, "sum"), ValueData::Int(15));
+        assert_eq!(variable(&interpreter, "product"), ValueData::Int(24));
+        assert_eq!(variable(&interpreter, "branch"), ValueData::Int(3));
+    }
+
+    #[test]
+    fn blocks_have_their_own_scope(){
+        let interpreter = run("
+            let a = 1
+            let b = 1
+            {
+                let a = 2
+                b = a
+            }
+        ");
+
+        assert_eq!(variable(&interpreter, "a"), ValueData::Int(1));
+        assert_eq!(variable(&interpreter, "b"), ValueData::Int(2));
+    }
+
+    #[test]
+    fn comparisons_and_logic(){
+        let interpreter = run("
+            let a = 1 < 2 && 2 <= 2 && !(3 > 4)
+            let b = 1 == 2 || 5 >= 6
+            let c = true != false
+            let d = false && 1 / 0 == 0
+        ");
+
+        assert_eq!(variable(&interpreter, "a"), ValueData::Bool(true));
+        assert_eq!(variable(&interpreter, "b"), ValueData::Bool(false));
+        assert_eq!(variable(&interpreter, "c"), ValueData::Bool(true));
+        assert_eq!(variable(&interpreter, "d"), ValueData::Bool(false));
+    }
+
+    #[test]
+    fn vectors(){
+        let interpreter = run("
+            let a = Vec2(1, 2)
+            let b = a + Vec2(3, 4) * Vec2(2, 1)
+            let c = -b * a.y
+            let x = c.x
+            let same = a == Vec2(1, 2)
+        ");
+
+        assert_eq!(variable(&interpreter, "b"), ValueData::Vec2(Vec2::new(7.0, 6.0)));
+        assert_eq!(variable(&interpreter, "c"), ValueData::Vec2(Vec2::new(-14.0, -12.0)));
+        assert_eq!(variable(&interpreter, "x"), ValueData::Float(-14.0));
+        assert_eq!(variable(&interpreter, "same"), ValueData::Bool(true));
+    }
+
+    #[test]
+    fn floats(){
+        let interpreter = run("
+            let max_speed = 100.0
+            let a = 1 + 0.5
+            let b = 3 -0.5
+            let c = float(3) / 2
+            let d = int(2.7)
+            let e = Vec2(1.0, 2.5) * 2
+            let f = 0.5 < 1
+        ");
+
+        assert_eq!(variable(&interpreter, "max_speed"), ValueData::Float(100.0));
+        assert_eq!(variable(&interpreter, "a"), ValueData::Float(1.5));
+        assert_eq!(variable(&interpreter, "b"), ValueData::Float(2.5));
+        assert_eq!(variable(&interpreter, "c"), ValueData::Float(1.5));
+        assert_eq!(variable(&interpreter, "d"), ValueData::Int(2));
+        assert_eq!(variable(&interpreter, "e"), ValueData::Vec2(Vec2::new(2.0, 5.0)));
+        assert_eq!(variable(&interpreter, "f"), ValueData::Bool(true));
+    }
+
+    #[test]
+    fn functions(){
+        let interpreter = run("
+            let scale = 2
+            fn scaled(v: Vec2, factor: float) -> Vec2 {
+                return v * factor * scale
+            }
+            fn first_above(limit: int) -> int {
+                for (let i = 0; i < 100; i = i + 1) {
+                    if i * i > limit {
+                        return i
+                    }
+                }
+                return -1
+            }
+            fn half(a: float) -> float { return a / 2 }
+            let a = scaled(Vec2(1.0, 2.0), 3)
+            let b = first_above(50)
+            let c = length(normalize(Vec2(3.0, 4.0)))
+            let d = half(3)
+            let e = normalize(Vec2(0.0, 0.0))
+        ");
+
+        assert_eq!(variable(&interpreter, "a"), ValueData::Vec2(Vec2::new(6.0, 12.0)));
+        assert_eq!(variable(&interpreter, "b"), ValueData::Int(8));
+        assert_eq!(variable(&interpreter, "c"), ValueData::Float(1.0));
+        assert_eq!(variable(&interpreter, "d"), ValueData::Float(1.5));
+        assert_eq!(variable(&interpreter, "e"), ValueData::Vec2(Vec2::ZERO));
+    }
+}
\ No newline at end of file
@@ -1,4 +1,4 @@
-use std::{iter::{self}};
+use std::{fmt::Display, iter::{self}};
 use itertools::{Itertools, MultiPeek, PeekingNext};
 
 
@@ -64,23 +64,25 @@ impl<I: Iterator<Item = char> + PeekingNext> PeekingNext for PositionTrackedChar
 
 #[derive(Debug, Clone)]
 pub struct LexerError{
-    line: u32,
-    column: u32,
-    message: String
+    pub span: Span,
+    pub message: String
+}
+
+impl Display for LexerError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.message)
+    }
 }
 
 pub struct Lexer<I>
 where I: Iterator<Item = char>{
     source: PositionTrackedChars<MultiPeek<I>>,
     pub error: Option<LexerError>,
-
-    line: i32,
-    column: i32,
 }
 
 impl<I: Iterator<Item = char>> Lexer<I>{
     pub fn new(source: I) -> Self{
-        Self { source: PositionTrackedChars::new(source.multipeek()), error: None, line: 0, column: 0 }
+        Self { source: PositionTrackedChars::new(source.multipeek()), error: None }
     }
 }
 
@@ -88,21 +90,12 @@ impl<I: Iterator<Item = char>> Iterator for Lexer<I>{
     type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(mut char) = self.source.next(){
+        if let Some(char) = self.source.next(){
             let position = self.source.position();
 
             const WHITESPACE_START: char = 0u8 as char;
             const WHITESPACE_END: char = 32u8 as char;
 
-            let is_negative_number = if matches!(char, '-') && self.source.peek().is_some_and(|c| matches!(c, '0'..='9')){
-                char = self.source.next().unwrap();
-                true
-            }else{
-                false
-            };
-
-            self.source.reset_peek();
-
             let token_type = match char{
                 WHITESPACE_START..=WHITESPACE_END => {return self.next()},
                 '(' => TokenType::OpeningParenthesis,
@@ -110,37 +103,51 @@ impl<I: Iterator<Item = char>> Iterator for Lexer<I>{
                 '{' => TokenType::OpeningCurlyBrace,
                 '}' => TokenType::ClosingCurlyBrace,
                 '+' => TokenType::Plus,
-                '-' => TokenType::Minus,
+                '-' => if self.source.peeking_next(|c| *c == '>').is_some() { TokenType::Arrow } else { TokenType::Minus },
                 '*' => TokenType::Asterisk,
                 '/' => TokenType::Slash,
                 ';' => TokenType::Semicolon,
+                ':' => TokenType::Colon,
+                ',' => TokenType::Comma,
                 '.' => TokenType::Dot,
                 c @ '0'..='9' => {
                     let mut value: f64 = 0.0;
-                    for c in iter::once(c).chain(self.source.peeking_take_while(|c| matches!(c, '0'..='9'))){
+                    for c in iter::once(c).chain(self.source.peeking_take_while(|c| c.is_ascii_digit())){
                         let digit = c as u8 - 48u8;
-                        println!("Adding digit: {digit}");
                         value = value * 10.0 + digit as f64;
                     }
-                    println!("Dot");
                     self.source.reset_peek();
                     if matches!(self.source.peek(), Some('.')){
                         self.source.next(); // skip the dot
                         // Add digits after point
                         let mut digits_after_point = 0;
-                        for c in self.source.peeking_take_while(|c| matches!(c, '0'..='9' )){
+                        for c in self.source.peeking_take_while(|c| c.is_ascii_digit()){
                             let digit = c as u8 - 48u8;
-                            println!("Adding digit: {digit}");
                             value = value * 10.0 + digit as f64;
                             digits_after_point += 1;
                         }
                         value /= 10_i32.pow(digits_after_point) as f64;
-                        TokenType::FloatLiteral(value as f32 * (if is_negative_number{-1.0} else {1.0} ))
+                        TokenType::FloatLiteral(value as f32)
+                    }else if value > MAX_INT_LITERAL as f64{
+                        self.error = Some(LexerError{
+                            span: Span{
+                                line: position.0,
+                                column: position.1,
+                                length: self.source.position().1 - position.1 + 1,
+                            },
+                            message: "Integer literal out of range".to_owned()
+                        });
+                        return None;
                     }else{
-                        TokenType::IntLiteral(value as i32 * (if is_negative_number{-1} else {1}))
+                        TokenType::IntLiteral(value as u32)
                     }
                 },
-                '=' => TokenType::Equals,
+                '=' => if self.source.peeking_next(|c| *c == '=').is_some() { TokenType::DoubleEquals } else { TokenType::Equals },
+                '!' => if self.source.peeking_next(|c| *c == '=').is_some() { TokenType::NotEquals } else { TokenType::ExclamationMark },
+                '<' => if self.source.peeking_next(|c| *c == '=').is_some() { TokenType::LessThanOrEquals } else { TokenType::LessThan },
+                '>' => if self.source.peeking_next(|c| *c == '=').is_some() { TokenType::GreaterThanOrEquals } else { TokenType::GreaterThan },
+                '&' if self.source.peeking_next(|c| *c == '&').is_some() => TokenType::DoubleAmpersand,
+                '|' if self.source.peeking_next(|c| *c == '|').is_some() => TokenType::DoublePipe,
                 first @ ('A'..='Z' | 'a'..='z' | '_') => {
                     let word = iter::once(first).chain(self.source.peeking_take_while(|c| matches!(c, 'A'..='Z' | 'a'..='z' | '_' | '0'..='9'))).collect::<String>();
 
@@ -150,25 +157,34 @@ impl<I: Iterator<Item = char>> Iterator for Lexer<I>{
                         "let" => TokenType::Let,
                         "while" => TokenType::While,
                         "for" => TokenType::For,
+                        "fn" => TokenType::Fn,
+                        "return" => TokenType::Return,
+                        "true" => TokenType::BoolLiteral(true),
+                        "false" => TokenType::BoolLiteral(false),
                         _ => TokenType::Identifier(word)
                     }
                 }
-                c @ _ =>{
-                    // TODO: Only show error source until the first whitespace character
+                c =>{
+                    let source = iter::once(c).chain(self.source.peeking_take_while(|c| !c.is_whitespace())).collect::<String>();
                     self.error = Some(LexerError{
-                        line: 0,
-                        column: 0,
-                        message: format!("Unknown token '{}'", iter::once(c).chain(self.source.by_ref()).collect::<String>())
+                        span: Span{
+                            line: position.0,
+                            column: position.1,
+                            length: source.chars().count() as u32,
+                        },
+                        message: format!("Unknown token '{source}'")
                     });
                     return None;
                 }
                     
             };
 
+            // Tokens never span multiple lines, so the token ends on the line it started on
             Some(Token{
                 token_type,
                 line: position.0,
-                column: position.1
+                column: position.1,
+                length: self.source.position().1 - position.1 + 1,
             })
         }else{
             None
@@ -178,6 +194,7 @@ impl<I: Iterator<Item = char>> Iterator for Lexer<I>{
 
 /// Performs lexical analysis on a &str and returns a vec of tokens.
 /// This is for quick testing. Use `Lexer` instead of this for an iterative aproach
+#[cfg(test)]
 fn lex(source: &str) -> Result<Vec<Token>, LexerError>{
     let mut lexer = Lexer::new(source.chars());
     let tokens = lexer.by_ref().collect::<Vec<Token>>();
@@ -187,29 +204,66 @@ fn lex(source: &str) -> Result<Vec<Token>, LexerError>{
         Ok(tokens)
     }
 }
+/// The largest integer literal. Only `i32::MIN` needs it, which is written with a minus in front of it.
+pub const MAX_INT_LITERAL: u32 = i32::MIN.unsigned_abs();
+
 #[derive(PartialEq, Clone, Debug)]
 pub struct Token{
     pub line: u32,
     pub column: u32,
+    /// The number of characters the token takes up in the source
+    pub length: u32,
     pub token_type: TokenType
 }
 
+impl Token{
+    pub fn span(&self) -> Span{
+        Span{
+            line: self.line,
+            column: self.column,
+            length: self.length,
+        }
+    }
+}
+
+/// A range of characters on a single line of source code
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Span{
+    pub line: u32,
+    pub column: u32,
+    pub length: u32,
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum TokenType{
     Identifier(String),
-    IntLiteral(i32),
+    /// Never negative, the parser applies the minus in front of it
+    IntLiteral(u32),
     FloatLiteral(f32),
+    BoolLiteral(bool),
     OpeningParenthesis,
     ClosingParenthesis,
     OpeningCurlyBrace,
     ClosingCurlyBrace,
     Semicolon,
+    Colon,
+    Comma,
     Equals,
     Dot,
     Plus,
     Minus,
     Asterisk,
     Slash,
+    DoubleEquals,
+    NotEquals,
+    LessThan,
+    LessThanOrEquals,
+    GreaterThan,
+    GreaterThanOrEquals,
+    DoubleAmpersand,
+    DoublePipe,
+    ExclamationMark,
+    Arrow,
 
     // Keywords:
     If,
@@ -217,14 +271,57 @@ pub enum TokenType{
     While,
     Let,
     For,
+    Fn,
+    Return,
+}
+
+impl Display for TokenType{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self{
+            TokenType::Identifier(name) => write!(f, "{name}"),
+            TokenType::IntLiteral(v) => write!(f, "{v}"),
+            TokenType::FloatLiteral(v) => write!(f, "{v:?}"),
+            TokenType::BoolLiteral(v) => write!(f, "{v}"),
+            TokenType::OpeningParenthesis => write!(f, "("),
+            TokenType::ClosingParenthesis => write!(f, ")"),
+            TokenType::OpeningCurlyBrace => write!(f, "{{"),
+            TokenType::ClosingCurlyBrace => write!(f, "}}"),
+            TokenType::Semicolon => write!(f, ";"),
+            TokenType::Colon => write!(f, ":"),
+            TokenType::Comma => write!(f, ","),
+            TokenType::Equals => write!(f, "="),
+            TokenType::Dot => write!(f, "."),
+            TokenType::Plus => write!(f, "+"),
+            TokenType::Minus => write!(f, "-"),
+            TokenType::Asterisk => write!(f, "*"),
+            TokenType::Slash => write!(f, "/"),
+            TokenType::DoubleEquals => write!(f, "=="),
+            TokenType::NotEquals => write!(f, "!="),
+            TokenType::LessThan => write!(f, "<"),
+            TokenType::LessThanOrEquals => write!(f, "<="),
+            TokenType::GreaterThan => write!(f, ">"),
+            TokenType::GreaterThanOrEquals => write!(f, ">="),
+            TokenType::DoubleAmpersand => write!(f, "&&"),
+            TokenType::DoublePipe => write!(f, "||"),
+            TokenType::ExclamationMark => write!(f, "!"),
+            TokenType::Arrow => write!(f, "->"),
+            TokenType::If => write!(f, "if"),
+            TokenType::Else => write!(f, "else"),
+            TokenType::While => write!(f, "while"),
+            TokenType::Let => write!(f, "let"),
+            TokenType::For => write!(f, "for"),
+            TokenType::Fn => write!(f, "fn"),
+            TokenType::Return => write!(f, "return"),
+        }
+    }
 }
 
 #[cfg(test)]
 mod lexer_tests{
-    use crate::particlescript::lexer::{lex, LexerError, Token, TokenType};
+    use crate::particlescript::lexer::{lex, LexerError, Span, Token, TokenType};
 
     fn lex_to_types(source: &str) -> Result<Vec<TokenType>, LexerError>{
-        lex(source).and_then(|tokens| Ok(tokens.into_iter().map(|token| token.token_type).collect()))
+        lex(source).map(|tokens| tokens.into_iter().map(|token| token.token_type).collect())
     }
 
     #[test]
@@ -252,11 +349,13 @@ mod lexer_tests{
         )
     }
     #[test]
-    fn float_literals_negative(){
+    fn minus_is_never_part_of_a_literal(){
+        // The parser decides whether a minus negates or subtracts
         assert_eq!(
             lex_to_types("-1.25- 1.25").unwrap(),
             vec![
-                TokenType::FloatLiteral(-1.25),
+                TokenType::Minus,
+                TokenType::FloatLiteral(1.25),
                 TokenType::Minus,
                 TokenType::FloatLiteral(1.25)
             ]
@@ -266,11 +365,11 @@ mod lexer_tests{
     #[test]
     fn int_float_differentiation(){
         assert_eq!(
-            lex_to_types("1.25 1.00 -1 125").unwrap(),
+            lex_to_types("1.25 1.00 1 125").unwrap(),
             vec![
                 TokenType::FloatLiteral(1.25),
                 TokenType::FloatLiteral(1.0),
-                TokenType::IntLiteral(-1),
+                TokenType::IntLiteral(1),
                 TokenType::IntLiteral(125)
             ]
         )
@@ -296,7 +395,8 @@ mod lexer_tests{
             Token{
                 token_type: TokenType::Identifier("ln".to_owned()),
                 line: 1,
-                column: 1
+                column: 1,
+                length: 2,
             }
         );
 
@@ -305,7 +405,8 @@ mod lexer_tests{
             Token{
                 token_type: TokenType::IntLiteral(4),
                 line: 1,
-                column: 5
+                column: 5,
+                length: 1,
             }
         );
         assert_eq!(
@@ -313,8 +414,93 @@ mod lexer_tests{
             Token{
                 token_type: TokenType::ClosingParenthesis,
                 line: 3,
-                column: 2
+                column: 2,
+                length: 1,
             }
         );
     }
-}
+
+    #[test]
+    fn spans_cover_the_source_text(){
+        let tokens = lex("a >= 1.50 + 0.30000 -> trueish").unwrap();
+        let lengths = tokens.iter().map(|token| token.span().length).collect::<Vec<_>>();
+        assert_eq!(lengths, vec![1, 2, 4, 1, 7, 2, 7]);
+        assert_eq!(tokens[4].span(), Span{ line: 1, column: 13, length: 7 });
+    }
+
+    #[test]
+    fn int_literal_range(){
+        assert_eq!(lex_to_types("2147483648").unwrap(), vec![TokenType::IntLiteral(2147483648)]);
+
+        let error = lex("let x = 99999999999").unwrap_err();
+        assert_eq!(error.span, Span{ line: 1, column: 9, length: 11 });
+        assert_eq!(error.message, "Integer literal out of range");
+        assert!(lex("2147483649").is_err());
+    }
+
+    #[test]
+    fn unknown_token_error(){
+        let error = lex("let a = 1\n  b = 2 $€ 4").unwrap_err();
+        assert_eq!(error.span, Span{ line: 2, column: 9, length: 2 });
+        assert_eq!(error.message, "Unknown token '$€'");
+    }
+
+    #[test]
+    fn comparison_and_logic_operators(){
+        assert_eq!(
+            lex_to_types("a<=b == !c&&d||e != f<-1 >= >").unwrap(),
+            vec![
+                TokenType::Identifier(String::from("a")),
+                TokenType::LessThanOrEquals,
+                TokenType::Identifier(String::from("b")),
+                TokenType::DoubleEquals,
+                TokenType::ExclamationMark,
+                TokenType::Identifier(String::from("c")),
+                TokenType::DoubleAmpersand,
+                TokenType::Identifier(String::from("d")),
+                TokenType::DoublePipe,
+                TokenType::Identifier(String::from("e")),
+                TokenType::NotEquals,
+                TokenType::Identifier(String::from("f")),
+                TokenType::LessThan,
+                TokenType::Minus,
+                TokenType::IntLiteral(1),
+                TokenType::GreaterThanOrEquals,
+                TokenType::GreaterThan,
+            ]
+        )
+    }
+
+    #[test]
+    fn bool_literals(){
+        assert_eq!(
+            lex_to_types("true false trueish").unwrap(),
+            vec![
+                TokenType::BoolLiteral(true),
+                TokenType::BoolLiteral(false),
+                TokenType::Identifier(String::from("trueish")),
+            ]
+        )
+    }
+
+    #[test]
+    fn function_tokens(){
+        assert_eq!(
+            lex_to_types("fn f() -> int { return a-1 }").unwrap(),
+            vec![
+                TokenType::Fn,
+                TokenType::Identifier(String::from("f")),
+                TokenType::OpeningParenthesis,
+                TokenType::ClosingParenthesis,
+                TokenType::Arrow,
+                TokenType::Identifier(String::from("int")),
+                TokenType::OpeningCurlyBrace,
+                TokenType::Return,
+                TokenType::Identifier(String::from("a")),
+                TokenType::Minus,
+                TokenType::IntLiteral(1),
+                TokenType::ClosingCurlyBrace,
+            ]
+        )
+    }
+}
\ No newline at end of file
@@ -1,3 +1,7 @@
+pub mod builtins;
+pub mod diagnostics;
+pub mod interpreter;
 pub mod lexer;
 pub mod parser;
+pub mod typechecker;
 pub mod types;
\ No newline at end of file
@@ -1,136 +1,852 @@
-use std::{rc::Rc};
+use std::{cell::OnceCell, fmt::Display, rc::Rc};
 
-use crate::particlescript::{lexer::{Token, TokenType::*}, types::{base_types, Type, Value, ValueData}};
-use itertools::{Itertools, MultiPeek};
+use crate::particlescript::{builtins::builtin_functions, lexer::{Span, Token, TokenType::*, MAX_INT_LITERAL}, types::{base_types, Type, Value, ValueData}};
+use itertools::MultiPeek;
 
 
+/// A whole script: the list of top-level statements in source order
+#[derive(Debug)]
+pub struct Program{
+    pub statements: Vec<Stmt>
+}
+
 #[derive(Debug)]
 pub enum Stmt{
     Assignment{
         variable: Rc<Variable>,
         value: Box<Stmt>,
+        /// Points at the variable name
+        span: Span,
+    },
+    Literal(Value),
+    VariableReference(Rc<Variable>),
+    BinaryOperation{
+        operator: BinaryOperator,
+        lhs: Box<Stmt>,
+        rhs: Box<Stmt>,
+        /// Points at the operator
+        span: Span,
+    },
+    Negation{
+        operand: Box<Stmt>,
+        span: Span,
+    },
+    Not{
+        operand: Box<Stmt>,
+        span: Span,
+    },
+    /// Creates a value of a type from its components, like `Vec2(1.0, 2.0)`,
+    /// or converts a number, like `float(1)`
+    Construct{
+        typ: Rc<Type>,
+        arguments: Vec<Stmt>,
+        /// Points at the type name
+        span: Span,
+    },
+    FieldAccess{
+        object: Box<Stmt>,
+        field: String,
+        /// Points at the field name
+        span: Span,
+    },
+    Block(Vec<Stmt>),
+    If{
+        condition: Box<Stmt>,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+        /// Points at the start of the condition
+        span: Span,
+    },
+    While{
+        condition: Box<Stmt>,
+        body: Box<Stmt>,
+        /// Points at the start of the condition
+        span: Span,
+    },
+    For{
+        initializer: Box<Stmt>,
+        condition: Box<Stmt>,
+        step: Box<Stmt>,
+        body: Box<Stmt>,
+        /// Points at the start of the condition
+        span: Span,
+    },
+    /// Declaring a function only makes it callable, the body is run by `Call`
+    FunctionDeclaration{
+        function: Rc<Function>,
+        /// Points at the function name
+        span: Span,
+    },
+    Call{
+        function: Rc<Function>,
+        arguments: Vec<Stmt>,
+        /// Points at the function name
+        span: Span,
+    },
+    Return{
+        value: Option<Box<Stmt>>,
+        /// The declared return type of the function this returns from
+        return_type: Rc<Type>,
+        /// Points at the `return` keyword
+        span: Span,
     },
-    Literal(Value)
 }
 
-impl Stmt{
-    fn return_type(&self, scope: &Scope) -> Rc<Type>{
+#[derive(Debug)]
+pub struct Function{
+    pub name: String,
+    pub parameters: Vec<Rc<Variable>>,
+    pub return_type: Rc<Type>,
+    pub body: FunctionBody,
+}
+
+#[derive(Debug)]
+pub enum FunctionBody{
+    /// The block of a function declared in a script
+    Script(Stmt),
+    /// A function implemented in Rust. Returns `None` if the arguments don't fit.
+    Builtin(fn(&[ValueData]) -> Option<ValueData>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator{
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    And,
+    Or,
+}
+
+impl BinaryOperator{
+    /// Operators with a higher precedence bind stronger
+    fn precedence(&self) -> u8{
         match self{
-            Stmt::Assignment{variable: _, value: _} => scope.find_type("void").unwrap(),
-            Stmt::Literal(val) => val.typ.clone()
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equal | BinaryOperator::NotEqual => 3,
+            BinaryOperator::Less | BinaryOperator::LessOrEqual | BinaryOperator::Greater | BinaryOperator::GreaterOrEqual => 4,
+            BinaryOperator::Add | BinaryOperator::Subtract => 5,
+            BinaryOperator::Multiply | BinaryOperator::Divide => 6,
         }
     }
+
+}
+
+impl Display for BinaryOperator{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self{
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessOrEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterOrEqual => ">=",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Or => "||",
+        })
+    }
 }
 
 macro_rules! match_tokens {
     ( $tokens:expr, $( $x:pat_param ),* ) => {
 
         $( 
-            let Some(t) = $tokens.peek() else { return Option::<Stmt>::None};
-            let $x = t.token_type.clone() else {return Option::<Stmt>::None};
+            let Some(t) = $tokens.peek() else { return Ok(None) };
+            let $x = t.token_type.clone() else { return Ok(None) };
         )*
 
 
         $(
-            // We gotta use $x somewhere, let's just hope the compiler optimizes this away
-            matches!(Semicolon, $x);
+            // We gotta use $x somewhere, stringify doesn't bind anything
+            let _ = stringify!($x);
             $tokens.next().unwrap();
         )*
     };
 }
 
+#[derive(Debug, Clone)]
+pub struct ParseError{
+    pub message: String,
+    /// The token the parser failed at. `None` if the source ended too early.
+    pub token: Option<Token>,
+    /// Descriptions of what would have been valid instead
+    pub expected: Vec<String>,
+}
+
+impl ParseError{
+    pub fn span(&self) -> Option<Span>{
+        self.token.as_ref().map(Token::span)
+    }
+
+    /// The message including the list of expected alternatives
+    pub fn full_message(&self) -> String{
+        if self.expected.is_empty(){
+            self.message.clone()
+        }else{
+            format!("{}, expected {}", self.message, self.expected.join(" or "))
+        }
+    }
+}
+
+impl Display for ParseError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(span) = self.span(){
+            write!(f, "{}:{}: ", span.line, span.column)?;
+        }
+        write!(f, "{}", self.full_message())
+    }
+}
+
 #[derive(Debug)]
-struct Variable{
-    name: String,
-    typ: Rc<Type>,
+pub struct Variable{
+    pub name: String,
+    /// Set by the parser if the type is annotated, otherwise inferred by the type checker
+    pub typ: OnceCell<Rc<Type>>,
 }
 
 
 pub struct Scope{
     variables: Vec<Rc<Variable>>,
     types: Vec<Rc<Type>>,
+    functions: Vec<Rc<Function>>,
+    /// Set in the scope of a function body, so that `return` knows what to return
+    return_type: Option<Rc<Type>>,
     parent_scope: Option<Box<Scope>>,
 }
 
 impl Scope{
     pub fn root() -> Self{
-        Self { variables: vec![], types: base_types().into_iter().map(|t| Rc::new(t)).collect(), parent_scope: None }
+        let mut scope = Self::empty();
+        scope.types = base_types().into_iter().map(Rc::new).collect();
+        scope.functions = builtin_functions(&scope).into_iter().map(Rc::new).collect();
+        scope
+    }
+
+    fn empty() -> Self{
+        Self { variables: vec![], types: vec![], functions: vec![], return_type: None, parent_scope: None }
     }
 
     fn find_variable(&self, name: &str) -> Option<Rc<Variable>>{
-        self.variables.iter().find(|v| v.name == name).map(Clone::clone).or_else(||{
+        self.variables.iter().rev().find(|v| v.name == name).map(Clone::clone).or_else(||{
             self.parent_scope.as_ref().and_then(|p| p.find_variable(name))
         })
     }
 
-    fn declare_variable(&mut self, name: String, typ: Rc<Type>) -> Rc<Variable>{
+    fn declare_variable(&mut self, name: String, typ: Option<Rc<Type>>) -> Rc<Variable>{
         let variable = Rc::new(Variable{
             name,
-            typ
+            typ: typ.map(OnceCell::from).unwrap_or_default()
         });
         self.variables.push(variable.clone());
         variable
     }
 
-    fn find_type(&self, name: &str) -> Option<Rc<Type>>{
+    pub fn find_type(&self, name: &str) -> Option<Rc<Type>>{
         self.types.iter().find(|t| t.name == name).map(Clone::clone).or_else(|| self.parent_scope.as_ref().and_then(|p| p.find_type(name)))
     }
+
+    pub fn find_function(&self, name: &str) -> Option<Rc<Function>>{
+        self.functions.iter().find(|f| f.name == name).map(Clone::clone).or_else(|| self.parent_scope.as_ref().and_then(|p| p.find_function(name)))
+    }
+
+    /// The return type of the function whose body this scope belongs to
+    fn return_type(&self) -> Option<Rc<Type>>{
+        self.return_type.clone().or_else(|| self.parent_scope.as_ref().and_then(|p| p.return_type()))
+    }
+
+    /// Runs `f` with this scope replaced by a new child scope, so that variables declared
+    /// inside of `f` are only visible there.
+    fn in_child_scope<R>(&mut self, f: impl FnOnce(&mut Scope) -> R) -> R{
+        let parent = std::mem::replace(self, Scope::empty());
+        self.parent_scope = Some(Box::new(parent));
+
+        let result = f(self);
+
+        let parent = self.parent_scope.take().expect("Child scope lost its parent");
+        *self = *parent;
+        result
+    }
+
+    /// All types visible from this scope, including the ones of parent scopes
+    pub fn types(&self) -> Box<dyn Iterator<Item = &Rc<Type>> + '_>{
+        match &self.parent_scope{
+            Some(parent) => Box::new(self.types.iter().chain(parent.types())),
+            None => Box::new(self.types.iter()),
+        }
+    }
 }
 
 
-type ParserFn = fn() -> bool;
+/// Parses statements until the end of the token stream.
+/// Statements can be separated by semicolons or just be written on separate lines.
+pub fn parse_program<T>(
+    tokens: &mut MultiPeek<T>,
+    scope: &mut Scope
+) -> Result<Program, ParseError>
+where T: Iterator<Item = Token>{
+    let mut statements = vec![];
+
+    loop{
+        match tokens.peek(){
+            None => break,
+            Some(Token{ token_type: Semicolon, ..}) => {
+                tokens.next();
+                continue;
+            }
+            Some(_) => tokens.reset_peek(),
+        }
+
+        statements.push(parse(tokens, scope)?);
+    }
+
+    Ok(Program { statements })
+}
+
+// `TokenType::Fn` shadows the trait here
+type ParserFn<T> = Box<dyn std::ops::Fn(&mut Scope, &mut MultiPeek<T>) -> Result<Option<Stmt>, ParseError>>;
 pub fn parse<T>(
     tokens: &mut MultiPeek<T>,
     scope: &mut Scope
-) -> Option<Stmt>
+) -> Result<Stmt, ParseError>
 where T: Iterator<Item = Token>{
     
 
-    let parsers: [Box<dyn Fn(&mut Scope, &mut MultiPeek<T>) -> Option<Stmt>>; _] = [Box::new(|scope, tokens|{
+    let parsers: [ParserFn<T>; _] = [Box::new(|scope, tokens|{
         match_tokens!(tokens,
-            Let,
-            Identifier(var_name),
-            Equals
+            Let
         );
 
-        let expr = parse(tokens, scope).expect("No expression after variable declaration");
+        let (var_name, span) = expect(tokens, "variable name", |t| match &t.token_type{
+            Identifier(name) => Some((name.clone(), t.span())),
+            _ => None,
+        })?;
+
+        let typ = match expect(tokens, "':' or '='", |t| match t.token_type{
+            Colon => Some(true),
+            Equals => Some(false),
+            _ => None,
+        })?{
+            true => {
+                let typ = parse_type(tokens, scope)?;
+                expect(tokens, "'='", |t| matches!(t.token_type, Equals).then_some(()))?;
+                Some(typ)
+            },
+            false => None,
+        };
+
+        let Some(expr) = parse_expression(tokens, scope)? else { return Err(unexpected(tokens, &["expression"])) };
+
+        let variable = scope.declare_variable(var_name, typ);
+        Ok(Some(Stmt::Assignment { variable, value: Box::new(expr), span }))
+    }),
+    Box::new(|scope, tokens|{
+        let Some(token @ Token{ token_type: Identifier(_), ..}) = tokens.peek().cloned() else { return Ok(None) };
+        if !matches!(tokens.peek(), Some(Token{ token_type: Equals, ..})){
+            return Ok(None);
+        }
+        tokens.next();
+        tokens.next();
+
+        let variable = find_variable(scope, &token)?;
+        let Some(expr) = parse_expression(tokens, scope)? else { return Err(unexpected(tokens, &["expression"])) };
 
-        let variable = scope.declare_variable(var_name, scope.find_type("int").unwrap());
-        Some(Stmt::Assignment { variable, value: Box::new(expr)})
+        Ok(Some(Stmt::Assignment { variable, value: Box::new(expr), span: token.span() }))
+    }),
+    Box::new(|scope, tokens|{
+        match_tokens!(tokens,
+            If
+        );
+        parse_if(tokens, scope).map(Some)
     }),
     Box::new(|scope, tokens|{
         match_tokens!(tokens,
-            IntLiteral(v)
+            While
         );
 
-        Some(Stmt::Literal(Value{
-            typ: scope.find_type("int").unwrap(),
-            data: ValueData::Int(v)
-        }))
-    })];
+        let span = next_span(tokens);
+        let condition = parse_expression(tokens, scope)?.ok_or_else(|| unexpected(tokens, &["condition"]))?;
+        let body = parse_block(tokens, scope)?;
+
+        Ok(Some(Stmt::While { condition: Box::new(condition), body: Box::new(body), span }))
+    }),
+    Box::new(|scope, tokens|{
+        match_tokens!(tokens,
+            For,
+            OpeningParenthesis
+        );
+
+        // The loop variable is only visible inside of the loop
+        scope.in_child_scope(|scope|{
+            let initializer = parse(tokens, scope)?;
+            expect(tokens, "';'", |t| matches!(t.token_type, Semicolon).then_some(()))?;
+            let span = next_span(tokens);
+            let condition = parse_expression(tokens, scope)?.ok_or_else(|| unexpected(tokens, &["condition"]))?;
+            expect(tokens, "';'", |t| matches!(t.token_type, Semicolon).then_some(()))?;
+            let step = parse(tokens, scope)?;
+            expect(tokens, "')'", |t| matches!(t.token_type, ClosingParenthesis).then_some(()))?;
+            let body = parse_block(tokens, scope)?;
+
+            Ok(Some(Stmt::For {
+                initializer: Box::new(initializer),
+                condition: Box::new(condition),
+                step: Box::new(step),
+                body: Box::new(body),
+                span
+            }))
+        })
+    }),
+    Box::new(|scope, tokens|{
+        match_tokens!(tokens,
+            Fn
+        );
+
+        let (name, span) = expect(tokens, "function name", |t| match &t.token_type{
+            Identifier(name) => Some((name.clone(), t.span())),
+            _ => None,
+        })?;
+        expect(tokens, "'('", |t| matches!(t.token_type, OpeningParenthesis).then_some(()))?;
+
+        // Parameters are only visible inside of the body. The function itself is declared
+        // after its body, so it can't call itself.
+        let function = scope.in_child_scope(|scope|{
+            let mut parameters = vec![];
+            loop{
+                tokens.reset_peek();
+                if matches!(tokens.peek(), Some(Token{ token_type: ClosingParenthesis, ..})){
+                    tokens.next();
+                    break;
+                }
+                if !parameters.is_empty(){
+                    expect(tokens, "',' or ')'", |t| matches!(t.token_type, Comma).then_some(()))?;
+                }
+                let parameter = expect(tokens, "parameter name", |t| match &t.token_type{
+                    Identifier(name) => Some(name.clone()),
+                    _ => None,
+                })?;
+                expect(tokens, "':'", |t| matches!(t.token_type, Colon).then_some(()))?;
+                let typ = parse_type(tokens, scope)?;
+                parameters.push(scope.declare_variable(parameter, Some(typ)));
+            }
+
+            tokens.reset_peek();
+            let return_type = if matches!(tokens.peek(), Some(Token{ token_type: Arrow, ..})){
+                tokens.next();
+                parse_type(tokens, scope)?
+            }else{
+                scope.find_type("void").unwrap()
+            };
+
+            scope.return_type = Some(return_type.clone());
+            let body = parse_block(tokens, scope)?;
+            Ok(Function { name, parameters, return_type, body: FunctionBody::Script(body) })
+        })?;
+
+        let function = Rc::new(function);
+        scope.functions.push(function.clone());
+        Ok(Some(Stmt::FunctionDeclaration { function, span }))
+    }),
+    Box::new(|scope, tokens|{
+        let Some(token @ Token{ token_type: Return, ..}) = tokens.peek().cloned() else { return Ok(None) };
+        tokens.next();
+
+        let return_type = scope.return_type().ok_or_else(|| ParseError{
+            message: "'return' outside of a function".to_owned(),
+            token: Some(token.clone()),
+            expected: vec![],
+        })?;
+        let value = parse_expression(tokens, scope)?;
+
+        Ok(Some(Stmt::Return { value: value.map(Box::new), return_type, span: token.span() }))
+    }),
+    Box::new(|scope, tokens|{
+        tokens.reset_peek();
+        if !matches!(tokens.peek(), Some(Token{ token_type: OpeningCurlyBrace, ..})){
+            return Ok(None);
+        }
+        parse_block(tokens, scope).map(Some)
+    }),
+    Box::new(|scope, tokens| parse_expression(tokens, scope))];
 
     for parser in parsers{
-        if let Some(stmt) = parser(scope, tokens){
-            println!("Parsed stmt: {:#?}", stmt);
-            return Some(stmt);
+        if let Some(stmt) = parser(scope, tokens)?{
+            return Ok(stmt);
         }else{
             tokens.reset_peek();
         }
     }
 
-    if let Some(last_tok) = tokens.peek(){
-        panic!("Failed to parse statement at {}:{}", last_tok.line, last_tok.column);
+    Err(unexpected(tokens, &["statement", "expression"]))
+}
+
+/// Parses the rest of an if statement after the `if` keyword
+fn parse_if<T>(
+    tokens: &mut MultiPeek<T>,
+    scope: &mut Scope
+) -> Result<Stmt, ParseError>
+where T: Iterator<Item = Token>{
+    let span = next_span(tokens);
+    let condition = parse_expression(tokens, scope)?.ok_or_else(|| unexpected(tokens, &["condition"]))?;
+    let then_branch = parse_block(tokens, scope)?;
+
+    tokens.reset_peek();
+    let else_branch = if matches!(tokens.peek(), Some(Token{ token_type: Else, ..})){
+        tokens.next();
+        tokens.reset_peek();
+        if matches!(tokens.peek(), Some(Token{ token_type: If, ..})){
+            tokens.next();
+            Some(parse_if(tokens, scope)?)
+        }else{
+            Some(parse_block(tokens, scope)?)
+        }
     }else{
-        panic!("Unexpected end of file")
+        None
+    };
+    tokens.reset_peek();
+
+    Ok(Stmt::If {
+        condition: Box::new(condition),
+        then_branch: Box::new(then_branch),
+        else_branch: else_branch.map(Box::new),
+        span
+    })
+}
+
+/// Parses statements enclosed in curly braces. The statements get their own child scope.
+fn parse_block<T>(
+    tokens: &mut MultiPeek<T>,
+    scope: &mut Scope
+) -> Result<Stmt, ParseError>
+where T: Iterator<Item = Token>{
+    expect(tokens, "'{'", |t| matches!(t.token_type, OpeningCurlyBrace).then_some(()))?;
+
+    scope.in_child_scope(|scope|{
+        let mut statements = vec![];
+        loop{
+            match tokens.peek(){
+                None => return Err(unexpected(tokens, &["'}'"])),
+                Some(Token{ token_type: ClosingCurlyBrace, ..}) => {
+                    tokens.next();
+                    break;
+                }
+                Some(Token{ token_type: Semicolon, ..}) => {
+                    tokens.next();
+                    continue;
+                }
+                Some(_) => tokens.reset_peek(),
+            }
+
+            statements.push(parse(tokens, scope)?);
+        }
+        Ok(Stmt::Block(statements))
+    })
+}
+
+/// Parses an expression using precedence climbing.
+/// Returns `None` if the next token can't start an expression.
+fn parse_expression<T>(
+    tokens: &mut MultiPeek<T>,
+    scope: &mut Scope
+) -> Result<Option<Stmt>, ParseError>
+where T: Iterator<Item = Token>{
+    let Some(lhs) = parse_unary(tokens, scope)? else { return Ok(None) };
+    parse_binary_operations(tokens, scope, lhs, 0).map(Some)
+}
+
+fn parse_binary_operations<T>(
+    tokens: &mut MultiPeek<T>,
+    scope: &mut Scope,
+    mut lhs: Stmt,
+    min_precedence: u8
+) -> Result<Stmt, ParseError>
+where T: Iterator<Item = Token>{
+    loop{
+        tokens.reset_peek();
+        let Some(token) = tokens.peek() else { break };
+
+        let span = token.span();
+        let operator = match token.token_type{
+            Plus => BinaryOperator::Add,
+            Minus => BinaryOperator::Subtract,
+            Asterisk => BinaryOperator::Multiply,
+            Slash => BinaryOperator::Divide,
+            DoubleEquals => BinaryOperator::Equal,
+            NotEquals => BinaryOperator::NotEqual,
+            LessThan => BinaryOperator::Less,
+            LessThanOrEquals => BinaryOperator::LessOrEqual,
+            GreaterThan => BinaryOperator::Greater,
+            GreaterThanOrEquals => BinaryOperator::GreaterOrEqual,
+            DoubleAmpersand => BinaryOperator::And,
+            DoublePipe => BinaryOperator::Or,
+            _ => break,
+        };
+
+        if operator.precedence() < min_precedence{
+            break;
+        }
+        tokens.next();
+
+        let rhs = parse_unary(tokens, scope)?.ok_or_else(|| unexpected(tokens, &["operand"]))?;
+        let rhs = parse_binary_operations(tokens, scope, rhs, operator.precedence() + 1)?;
+
+        lhs = Stmt::BinaryOperation { operator, lhs: Box::new(lhs), rhs: Box::new(rhs), span };
+    }
+    tokens.reset_peek();
+    Ok(lhs)
+}
+
+fn parse_unary<T>(
+    tokens: &mut MultiPeek<T>,
+    scope: &mut Scope
+) -> Result<Option<Stmt>, ParseError>
+where T: Iterator<Item = Token>{
+    tokens.reset_peek();
+    let Some(token) = tokens.peek().cloned() else { return Ok(None) };
+
+    let stmt = match token.token_type{
+        Minus => {
+            tokens.next();
+            // The smallest int can only be written negated, without the minus it is out of range
+            if matches!(tokens.peek(), Some(Token{ token_type: IntLiteral(MAX_INT_LITERAL), ..})){
+                tokens.next();
+                return Ok(Some(Stmt::Literal(Value{
+                    typ: scope.find_type("int").unwrap(),
+                    data: ValueData::Int(i32::MIN)
+                })));
+            }
+            tokens.reset_peek();
+            let operand = parse_unary(tokens, scope)?.ok_or_else(|| unexpected(tokens, &["operand"]))?;
+            Stmt::Negation{ operand: Box::new(operand), span: token.span() }
+        },
+        ExclamationMark => {
+            tokens.next();
+            let operand = parse_unary(tokens, scope)?.ok_or_else(|| unexpected(tokens, &["operand"]))?;
+            Stmt::Not{ operand: Box::new(operand), span: token.span() }
+        },
+        _ => return parse_field_accesses(tokens, scope),
+    };
+    Ok(Some(stmt))
+}
+
+/// Parses a primary expression followed by any number of `.field` accesses
+fn parse_field_accesses<T>(
+    tokens: &mut MultiPeek<T>,
+    scope: &mut Scope
+) -> Result<Option<Stmt>, ParseError>
+where T: Iterator<Item = Token>{
+    let Some(mut stmt) = parse_primary(tokens, scope)? else { return Ok(None) };
+
+    loop{
+        tokens.reset_peek();
+        if !matches!(tokens.peek(), Some(Token{ token_type: Dot, ..})){
+            break;
+        }
+        tokens.next();
+
+        let (field, span) = expect(tokens, "field name", |t| match &t.token_type{
+            Identifier(name) => Some((name.clone(), t.span())),
+            _ => None,
+        })?;
+        stmt = Stmt::FieldAccess { object: Box::new(stmt), field, span };
+    }
+    tokens.reset_peek();
+    Ok(Some(stmt))
+}
+
+fn parse_primary<T>(
+    tokens: &mut MultiPeek<T>,
+    scope: &mut Scope
+) -> Result<Option<Stmt>, ParseError>
+where T: Iterator<Item = Token>{
+    tokens.reset_peek();
+    let Some(token) = tokens.peek().cloned() else { return Ok(None) };
+    let followed_by_parenthesis = matches!(tokens.peek(), Some(Token{ token_type: OpeningParenthesis, ..}));
+
+    let stmt = match token.token_type{
+        BoolLiteral(v) => {
+            tokens.next();
+            Stmt::Literal(Value{
+                typ: scope.find_type("bool").unwrap(),
+                data: ValueData::Bool(v)
+            })
+        },
+        IntLiteral(v) => {
+            let v = i32::try_from(v).map_err(|_| ParseError{
+                message: "Integer literal out of range".to_owned(),
+                token: Some(token.clone()),
+                expected: vec![],
+            })?;
+            tokens.next();
+            Stmt::Literal(Value{
+                typ: scope.find_type("int").unwrap(),
+                data: ValueData::Int(v)
+            })
+        },
+        FloatLiteral(v) => {
+            tokens.next();
+            Stmt::Literal(Value{
+                typ: scope.find_type("float").unwrap(),
+                data: ValueData::Float(v)
+            })
+        },
+        // A type name followed by parenthesis constructs or converts a value of that type
+        Identifier(ref name) if followed_by_parenthesis && scope.find_type(name).is_some() => {
+            let typ = scope.find_type(name).unwrap();
+            tokens.next();
+            tokens.next();
+
+            let arguments = parse_arguments(tokens, scope)?;
+            Stmt::Construct { typ, arguments, span: token.span() }
+        },
+        // Any other name followed by parenthesis calls a function
+        Identifier(ref name) if followed_by_parenthesis => {
+            let function = scope.find_function(name).ok_or_else(|| ParseError{
+                message: format!("Unknown function '{name}'"),
+                token: Some(token.clone()),
+                expected: vec![],
+            })?;
+            tokens.next();
+            tokens.next();
+
+            let arguments = parse_arguments(tokens, scope)?;
+            Stmt::Call { function, arguments, span: token.span() }
+        },
+        Identifier(_) => {
+            let variable = find_variable(scope, &token)?;
+            tokens.next();
+            Stmt::VariableReference(variable)
+        },
+        OpeningParenthesis => {
+            tokens.next();
+            let inner = parse_expression(tokens, scope)?.ok_or_else(|| unexpected(tokens, &["expression"]))?;
+            expect(tokens, "')'", |t| matches!(t.token_type, ClosingParenthesis).then_some(()))?;
+            inner
+        },
+        _ => {
+            // Whoever tries something else next has to see this token again
+            tokens.reset_peek();
+            return Ok(None)
+        },
+    };
+    Ok(Some(stmt))
+}
+
+/// Parses comma separated arguments up to and including the closing parenthesis
+fn parse_arguments<T>(
+    tokens: &mut MultiPeek<T>,
+    scope: &mut Scope
+) -> Result<Vec<Stmt>, ParseError>
+where T: Iterator<Item = Token>{
+    let mut arguments = vec![];
+    loop{
+        tokens.reset_peek();
+        if matches!(tokens.peek(), Some(Token{ token_type: ClosingParenthesis, ..})){
+            tokens.next();
+            break;
+        }
+        if !arguments.is_empty(){
+            expect(tokens, "',' or ')'", |t| matches!(t.token_type, Comma).then_some(()))?;
+        }
+        arguments.push(parse_expression(tokens, scope)?.ok_or_else(|| unexpected(tokens, &["argument"]))?);
+    }
+    Ok(arguments)
+}
+
+/// Parses a type name and resolves it
+fn parse_type<T>(
+    tokens: &mut MultiPeek<T>,
+    scope: &mut Scope
+) -> Result<Rc<Type>, ParseError>
+where T: Iterator<Item = Token>{
+    let typ = expect(tokens, "type name", |t| match &t.token_type{
+        Identifier(_) => Some(t.clone()),
+        _ => None,
+    })?;
+    find_type(scope, &typ)
+}
+
+/// Resolves the variable named by an identifier token
+fn find_variable(scope: &Scope, token: &Token) -> Result<Rc<Variable>, ParseError>{
+    let Identifier(name) = &token.token_type else { unreachable!("Variable names are identifiers") };
+    scope.find_variable(name).ok_or_else(|| ParseError{
+        message: format!("Unknown variable '{name}'"),
+        token: Some(token.clone()),
+        expected: vec![],
+    })
+}
+
+/// Resolves the type named by an identifier token
+fn find_type(scope: &Scope, token: &Token) -> Result<Rc<Type>, ParseError>{
+    let Identifier(name) = &token.token_type else { unreachable!("Type names are identifiers") };
+    scope.find_type(name).ok_or_else(|| ParseError{
+        message: format!("Unknown type '{name}'"),
+        token: Some(token.clone()),
+        expected: vec![],
+    })
+}
+
+/// The span of the next token. Used to point at the start of whatever gets parsed next.
+fn next_span<T>(tokens: &mut MultiPeek<T>) -> Span
+where T: Iterator<Item = Token>{
+    tokens.reset_peek();
+    // If there is no next token, parsing fails anyway and the span is never used
+    let span = tokens.peek().map_or(Span { line: 0, column: 0, length: 0 }, Token::span);
+    tokens.reset_peek();
+    span
+}
+
+/// Consumes the next token if `accept` maps it to a value, otherwise returns an error
+/// saying that `expected` was expected.
+fn expect<T, R>(
+    tokens: &mut MultiPeek<T>,
+    expected: &str,
+    accept: impl FnOnce(&Token) -> Option<R>
+) -> Result<R, ParseError>
+where T: Iterator<Item = Token>{
+    tokens.reset_peek();
+    match tokens.peek().and_then(accept){
+        Some(result) => {
+            tokens.next();
+            Ok(result)
+        },
+        None => Err(unexpected(tokens, &[expected])),
     }
 }
 
+/// Creates an error for the next token in the stream
+fn unexpected<T>(tokens: &mut MultiPeek<T>, expected: &[&str]) -> ParseError
+where T: Iterator<Item = Token>{
+    tokens.reset_peek();
+    let token = tokens.peek().cloned();
+    tokens.reset_peek();
+
+    ParseError{
+        message: match &token{
+            Some(token) => format!("Unexpected token '{}'", token.token_type),
+            None => "Unexpected end of file".to_owned(),
+        },
+        token,
+        expected: expected.iter().map(|e| e.to_string()).collect(),
+    }
+}
 
 #[cfg(test)]
 mod test{
     use std::rc::Rc;
 
-    use crate::particlescript::{lexer::{Token, TokenType}, parser::{parse, Scope, Stmt}, types::base_types};
+    use itertools::Itertools;
+
+    use crate::particlescript::{lexer::{Lexer, Span, Token, TokenType}, parser::{parse, parse_program, BinaryOperator, Scope, Stmt}, types::base_types};
 
 /*     #[test]
     fn match_tokens(){
@@ -176,23 +892,168 @@ mod test{
             TokenType::Identifier(String::from("a")),
             TokenType::Equals,
             TokenType::IntLiteral(5)
-        ].map(|t| Token{ line: 0, column: 0, token_type: t});
+        ].map(|t| Token{ line: 0, column: 0, length: 0, token_type: t});
 
         let mut scope = Scope{
             variables: vec![],
             types: base_types().into_iter().map(Rc::new).collect(),
+            functions: vec![],
+            return_type: None,
             parent_scope: None,
         };
-        let Some(stmt) = parse(tokens.into_iter(), &mut scope) else { panic!("Parser failed") };
+        let Ok(stmt) = parse(&mut tokens.into_iter().multipeek(), &mut scope) else { panic!("Parser failed") };
 
-        let Some(v) = scope.variables.get(0) else { panic!("Variable was not declared") };
+        let Some(v) = scope.variables.first() else { panic!("Variable was not declared") };
 
         match stmt {
-            Stmt::Assignment { variable, value } => {
+            Stmt::Assignment { variable, .. } => {
                 assert!(Rc::ptr_eq(v, &variable));
             }
             _ => panic!("Stmt is not an assignment"),
         }
         
     }
+
+    #[test]
+    fn parse_multiple_statements(){
+        let mut scope = Scope::root();
+        let mut tokens = Lexer::new("let a = 1; let b = 2\nlet c = 3;;\n".chars()).multipeek();
+        let program = parse_program(&mut tokens, &mut scope).unwrap();
+
+        assert_eq!(program.statements.len(), 3);
+        assert_eq!(scope.variables.iter().map(|v| v.name.as_str()).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn references_resolve_to_the_latest_declaration(){
+        let mut scope = Scope::root();
+        let mut tokens = Lexer::new("let a = 1; let a = a + 1; let b = a".chars()).multipeek();
+        let program = parse_program(&mut tokens, &mut scope).unwrap();
+
+        let Stmt::Assignment { variable: first, .. } = &program.statements[0] else { panic!("Stmt is not an assignment") };
+        let Stmt::Assignment { variable: second, value, .. } = &program.statements[1] else { panic!("Stmt is not an assignment") };
+        // The value of a shadowing declaration still sees the previous one
+        let Stmt::BinaryOperation { lhs, .. } = value.as_ref() else { panic!("Stmt is not a binary operation") };
+        assert!(matches!(lhs.as_ref(), Stmt::VariableReference(v) if Rc::ptr_eq(v, first)));
+
+        let Stmt::Assignment { value, .. } = &program.statements[2] else { panic!("Stmt is not an assignment") };
+        assert!(matches!(value.as_ref(), Stmt::VariableReference(v) if Rc::ptr_eq(v, second)));
+    }
+
+    #[test]
+    fn parse_operator_precedence(){
+        let mut scope = Scope::root();
+        let mut tokens = Lexer::new("1 + 2 * 3".chars()).multipeek();
+        let Ok(stmt) = parse(&mut tokens, &mut scope) else { panic!("Parser failed") };
+
+        let Stmt::BinaryOperation { operator: BinaryOperator::Add, rhs, .. } = stmt else { panic!("Addition is not the root operation") };
+        assert!(matches!(*rhs, Stmt::BinaryOperation { operator: BinaryOperator::Multiply, .. }));
+    }
+
+    #[test]
+    fn parse_minus(){
+        let parse_source = |source: &str| parse_program(&mut Lexer::new(source.chars()).multipeek(), &mut Scope::root()).unwrap().statements;
+
+        // A minus after an operand subtracts, even without a space before the number
+        let statements = parse_source("let a = 1\na -0.0");
+        let Stmt::BinaryOperation { operator: BinaryOperator::Subtract, rhs, span, .. } = &statements[1] else { panic!("Minus after an operand is not a subtraction") };
+        assert!(matches!(**rhs, Stmt::Literal(_)));
+        assert_eq!(*span, Span{ line: 2, column: 3, length: 1 });
+
+        let statements = parse_source("let a = 1; - -a");
+        let Stmt::Negation { operand, .. } = &statements[1] else { panic!("Leading minus is not a negation") };
+        assert!(matches!(**operand, Stmt::Negation { .. }));
+
+        // A number without a sign is never subtracted
+        let statements = parse_source("let a = 1\nlet b = a 0");
+        let Stmt::Assignment { value, .. } = &statements[1] else { panic!("Stmt is not an assignment") };
+        assert!(matches!(**value, Stmt::VariableReference(_)));
+    }
+
+    #[test]
+    fn parse_errors(){
+        let parse_source = |source: &str| parse_program(&mut Lexer::new(source.chars()).multipeek(), &mut Scope::root());
+
+        let error = parse_source("let = 5").unwrap_err();
+        assert_eq!(error.token.map(|t| t.token_type), Some(TokenType::Equals));
+        assert_eq!(error.expected, vec!["variable name"]);
+
+        let error = parse_source("let a = (1 + 2").unwrap_err();
+        assert_eq!(error.token, None);
+        assert_eq!(error.expected, vec!["')'"]);
+
+        let error = parse_source("let a = 1\nlet b = c").unwrap_err();
+        assert_eq!(error.span(), Some(Span{ line: 2, column: 9, length: 1 }));
+
+        let error = parse_source("let a: Vec3 = 1").unwrap_err();
+        assert_eq!(error.message, "Unknown type 'Vec3'");
+
+        // Only the smallest int may be written as large as this
+        let error = parse_source("let a = 2147483648").unwrap_err();
+        assert_eq!(error.message, "Integer literal out of range");
+        assert_eq!(error.span(), Some(Span{ line: 1, column: 9, length: 10 }));
+        assert!(parse_source("let a = 1 -2147483648").is_err());
+    }
+
+    #[test]
+    fn block_variables_are_not_visible_outside(){
+        let mut scope = Scope::root();
+        let error = parse_program(&mut Lexer::new("{ let a = 1 }\nlet b = a".chars()).multipeek(), &mut scope).unwrap_err();
+
+        assert_eq!(error.message, "Unknown variable 'a'");
+        assert!(scope.variables.is_empty());
+    }
+
+    #[test]
+    fn parse_logic_precedence(){
+        let mut scope = Scope::root();
+        let mut tokens = Lexer::new("1 < 2 && 3 + 1 == 4 || false".chars()).multipeek();
+        let Ok(stmt) = parse(&mut tokens, &mut scope) else { panic!("Parser failed") };
+
+        let Stmt::BinaryOperation { operator: BinaryOperator::Or, lhs, .. } = stmt else { panic!("Or is not the root operation") };
+        let Stmt::BinaryOperation { operator: BinaryOperator::And, rhs, .. } = *lhs else { panic!("And doesn't bind stronger than or") };
+        assert!(matches!(*rhs, Stmt::BinaryOperation { operator: BinaryOperator::Equal, .. }));
+    }
+
+    #[test]
+    fn parse_constructor_and_field_access(){
+        let mut scope = Scope::root();
+        let mut tokens = Lexer::new("-Vec2(1, 2 + 3).x".chars()).multipeek();
+        let Ok(Stmt::Negation { operand, .. }) = parse(&mut tokens, &mut scope) else { panic!("Field access binds weaker than negation") };
+
+        let Stmt::FieldAccess { object, field, .. } = *operand else { panic!("Stmt is not a field access") };
+        assert_eq!(field, "x");
+        let Stmt::Construct { typ, arguments, .. } = *object else { panic!("Stmt is not a constructor") };
+        assert_eq!(typ.name, "Vec2");
+        assert_eq!(arguments.len(), 2);
+    }
+
+    #[test]
+    fn parse_functions(){
+        let mut scope = Scope::root();
+        let program = parse_program(&mut Lexer::new("fn f(a: int, b: Vec2) -> Vec2 { return b * a }\nlet v = f(1, Vec2(1, 2))".chars()).multipeek(), &mut scope).unwrap();
+
+        let Stmt::FunctionDeclaration { function, .. } = &program.statements[0] else { panic!("Stmt is not a function declaration") };
+        assert_eq!(function.parameters.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(function.return_type.name, "Vec2");
+        assert!(scope.find_function("f").is_some());
+        assert!(scope.variables.iter().all(|v| v.name == "v"));
+
+        let Stmt::Assignment { value, .. } = &program.statements[1] else { panic!("Stmt is not an assignment") };
+        let Stmt::Call { function: called, arguments, .. } = value.as_ref() else { panic!("Stmt is not a call") };
+        assert!(Rc::ptr_eq(function, called));
+        assert_eq!(arguments.len(), 2);
+
+        let parse_source = |source: &str| parse_program(&mut Lexer::new(source.chars()).multipeek(), &mut Scope::root());
+
+        let error = parse_source("return 1").unwrap_err();
+        assert_eq!(error.message, "'return' outside of a function");
+
+        let error = parse_source("let a = g(1)").unwrap_err();
+        assert_eq!(error.message, "Unknown function 'g'");
+        assert_eq!(error.span(), Some(Span{ line: 1, column: 9, length: 1 }));
+
+        let error = parse_source("fn f() { return f() }").unwrap_err();
+        assert_eq!(error.message, "Unknown function 'f'");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,367 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::particlescript::{lexer::Span, parser::{BinaryOperator, FunctionBody, Program, Scope, Stmt}, types::Type};
+
+#[derive(Debug, Clone)]
+pub struct TypeError{
+    pub message: String,
+    pub span: Span,
+}
+
+impl Display for TypeError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.message)
+    }
+}
+
+/// Checks that every operation in the program is applied to values of fitting types.
+/// Variables declared without a type annotation get the type of their initializer.
+pub fn check(program: &Program, scope: &Scope) -> Result<(), TypeError>{
+    for stmt in &program.statements{
+        stmt.return_type(scope)?;
+    }
+    Ok(())
+}
+
+impl Stmt{
+    /// Type checks this statement and returns the type of the value it evaluates to
+    pub fn return_type(&self, scope: &Scope) -> Result<Rc<Type>, TypeError>{
+        let base_type = |name: &str| scope.find_type(name).unwrap_or_else(|| panic!("Base type '{name}' is missing"));
+
+        match self{
+            Stmt::Assignment { variable, value, span } => {
+                let value_type = value.return_type(scope)?;
+                let variable_type = variable.typ.get_or_init(|| value_type.clone());
+
+                if !assignable(&variable_type.name, &value_type.name){
+                    return Err(TypeError {
+                        message: format!("Cannot assign {} to variable '{}' of type {}", value_type.name, variable.name, variable_type.name),
+                        span: *span
+                    });
+                }
+                Ok(base_type("void"))
+            },
+            Stmt::Literal(value) => Ok(value.typ.clone()),
+            Stmt::VariableReference(variable) => Ok(variable.typ.get().expect("Variables are declared before they are used").clone()),
+            Stmt::BinaryOperation { operator, lhs, rhs, span } => {
+                let lhs = lhs.return_type(scope)?;
+                let rhs = rhs.return_type(scope)?;
+
+                binary_result_type(*operator, &lhs.name, &rhs.name).map(base_type).ok_or_else(|| TypeError {
+                    message: format!("Cannot apply '{}' to {} and {}", operator, lhs.name, rhs.name),
+                    span: *span
+                })
+            },
+            Stmt::Negation { operand, span } => {
+                let typ = operand.return_type(scope)?;
+                match typ.name.as_str(){
+                    "int" | "float" | "Vec2" => Ok(typ),
+                    _ => Err(TypeError { message: format!("Cannot negate {}", typ.name), span: *span }),
+                }
+            },
+            Stmt::Not { operand, span } => {
+                let typ = operand.return_type(scope)?;
+                match typ.name.as_str(){
+                    "bool" => Ok(typ),
+                    _ => Err(TypeError { message: format!("Cannot apply '!' to {}", typ.name), span: *span }),
+                }
+            },
+            Stmt::Construct { typ, arguments, span } => {
+                let parameters = constructor_parameters(&typ.name).ok_or_else(|| TypeError {
+                    message: format!("{} has no constructor", typ.name),
+                    span: *span
+                })?;
+                if arguments.len() != parameters.len(){
+                    return Err(TypeError {
+                        message: format!("{} takes {} arguments ({}), got {}", typ.name, parameters.len(), parameters.join(", "), arguments.len()),
+                        span: *span
+                    });
+                }
+                for (argument, parameter) in arguments.iter().zip(parameters){
+                    let argument_type = argument.return_type(scope)?;
+                    if !matches!(argument_type.name.as_str(), "int" | "float"){
+                        return Err(TypeError {
+                            message: format!("Argument '{}' of {} has to be a number, found {}", parameter, typ.name, argument_type.name),
+                            span: *span
+                        });
+                    }
+                }
+                Ok(typ.clone())
+            },
+            Stmt::FieldAccess { object, field, span } => {
+                let object_type = object.return_type(scope)?;
+                field_type(&object_type.name, field).map(base_type).ok_or_else(|| TypeError {
+                    message: format!("{} has no field '{}'", object_type.name, field),
+                    span: *span
+                })
+            },
+            Stmt::Block(statements) => {
+                for stmt in statements{
+                    stmt.return_type(scope)?;
+                }
+                Ok(base_type("void"))
+            },
+            Stmt::If { condition, then_branch, else_branch, span } => {
+                check_condition(condition, *span, scope)?;
+                then_branch.return_type(scope)?;
+                if let Some(else_branch) = else_branch{
+                    else_branch.return_type(scope)?;
+                }
+                Ok(base_type("void"))
+            },
+            Stmt::While { condition, body, span } => {
+                check_condition(condition, *span, scope)?;
+                body.return_type(scope)?;
+                Ok(base_type("void"))
+            },
+            Stmt::For { initializer, condition, step, body, span } => {
+                initializer.return_type(scope)?;
+                check_condition(condition, *span, scope)?;
+                step.return_type(scope)?;
+                body.return_type(scope)?;
+                Ok(base_type("void"))
+            },
+            Stmt::FunctionDeclaration { function, span } => {
+                if let FunctionBody::Script(body) = &function.body{
+                    body.return_type(scope)?;
+                    if function.return_type.name != "void" && !body.always_returns(){
+                        return Err(TypeError {
+                            message: format!("Function '{}' doesn't return a {} on every path", function.name, function.return_type.name),
+                            span: *span
+                        });
+                    }
+                }
+                Ok(base_type("void"))
+            },
+            Stmt::Call { function, arguments, span } => {
+                if arguments.len() != function.parameters.len(){
+                    let parameters = function.parameters.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+                    return Err(TypeError {
+                        message: format!("{} takes {} arguments ({}), got {}", function.name, parameters.len(), parameters.join(", "), arguments.len()),
+                        span: *span
+                    });
+                }
+                for (argument, parameter) in arguments.iter().zip(&function.parameters){
+                    let argument_type = argument.return_type(scope)?;
+                    let parameter_type = parameter.typ.get().expect("Parameters are always annotated");
+                    if !assignable(&parameter_type.name, &argument_type.name){
+                        return Err(TypeError {
+                            message: format!("Argument '{}' of {} has to be {}, found {}", parameter.name, function.name, parameter_type.name, argument_type.name),
+                            span: *span
+                        });
+                    }
+                }
+                Ok(function.return_type.clone())
+            },
+            Stmt::Return { value, return_type, span } => {
+                let value_type = match value{
+                    Some(value) => value.return_type(scope)?,
+                    None => base_type("void"),
+                };
+                if !assignable(&return_type.name, &value_type.name){
+                    return Err(TypeError {
+                        message: format!("Function has to return {}, found {}", return_type.name, value_type.name),
+                        span: *span
+                    });
+                }
+                Ok(base_type("void"))
+            },
+        }
+    }
+}
+
+impl Stmt{
+    /// Whether running this statement always ends in a `return`. Loops don't count,
+    /// because their body might not run at all.
+    fn always_returns(&self) -> bool{
+        match self{
+            Stmt::Return { .. } => true,
+            Stmt::Block(statements) => statements.iter().any(Stmt::always_returns),
+            Stmt::If { then_branch, else_branch: Some(else_branch), .. } => then_branch.always_returns() && else_branch.always_returns(),
+            _ => false,
+        }
+    }
+}
+
+fn check_condition(condition: &Stmt, span: Span, scope: &Scope) -> Result<(), TypeError>{
+    let typ = condition.return_type(scope)?;
+    if typ.name != "bool"{
+        return Err(TypeError { message: format!("Condition has to be a bool, found {}", typ.name), span });
+    }
+    Ok(())
+}
+
+/// Whether a value of type `value` can be stored where a `target` is expected.
+/// Ints are promoted when they are used as a float.
+fn assignable(target: &str, value: &str) -> bool{
+    target == value || (target == "float" && value == "int")
+}
+
+/// The names of the components a constructor of the given type takes
+fn constructor_parameters(typ: &str) -> Option<&'static [&'static str]>{
+    match typ{
+        "Vec2" => Some(&["x", "y"]),
+        // Explicit conversions between numbers
+        "int" | "float" => Some(&["value"]),
+        _ => None,
+    }
+}
+
+/// The name of the type of a field
+fn field_type(typ: &str, field: &str) -> Option<&'static str>{
+    match (typ, field){
+        ("Vec2", "x" | "y") => Some("float"),
+        _ => None,
+    }
+}
+
+/// The name of the type an operation on values of the given types results in.
+/// Returns `None` if the operator can't be applied to these types.
+fn binary_result_type(operator: BinaryOperator, lhs: &str, rhs: &str) -> Option<&'static str>{
+    // An int used together with a float or a vector is promoted to a float
+    let (lhs, rhs) = match (lhs, rhs){
+        ("int", "float" | "Vec2") => ("float", rhs),
+        ("float" | "Vec2", "int") => (lhs, "float"),
+        _ => (lhs, rhs),
+    };
+
+    let numeric = match (lhs, rhs){
+        ("int", "int") => Some("int"),
+        ("float", "float") => Some("float"),
+        _ => None,
+    };
+    let bools = lhs == "bool" && rhs == "bool";
+    // Vectors are combined component-wise, or scaled by a float
+    let vectors = match (operator, lhs, rhs){
+        (_, "Vec2", "Vec2") => Some("Vec2"),
+        (BinaryOperator::Multiply | BinaryOperator::Divide, "Vec2", "float") => Some("Vec2"),
+        (BinaryOperator::Multiply, "float", "Vec2") => Some("Vec2"),
+        _ => None,
+    };
+
+    match operator{
+        BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Multiply | BinaryOperator::Divide => numeric.or(vectors),
+        BinaryOperator::Less | BinaryOperator::LessOrEqual | BinaryOperator::Greater | BinaryOperator::GreaterOrEqual => numeric.map(|_| "bool"),
+        BinaryOperator::Equal | BinaryOperator::NotEqual => (numeric.is_some() || bools || vectors.is_some()).then_some("bool"),
+        BinaryOperator::And | BinaryOperator::Or => bools.then_some("bool"),
+    }
+}
+
+
+#[cfg(test)]
+mod test{
+    use itertools::Itertools;
+
+    use crate::particlescript::{lexer::{Lexer, Span}, parser::{parse_program, Program, Scope, Stmt}, typechecker::{check, TypeError}};
+
+    fn check_source(source: &str) -> Result<Program, TypeError>{
+        let mut scope = Scope::root();
+        let program = parse_program(&mut Lexer::new(source.chars()).multipeek(), &mut scope).unwrap();
+        check(&program, &scope).map(|_| program)
+    }
+
+    #[test]
+    fn infers_variable_types(){
+        let program = check_source("let a = 1 < 2\nlet b = -3 * 2\nlet c = a && !a").unwrap();
+
+        let types = program.statements.iter().map(|stmt| match stmt{
+            Stmt::Assignment { variable, .. } => variable.typ.get().unwrap().name.clone(),
+            _ => panic!("Stmt is not an assignment"),
+        }).collect::<Vec<_>>();
+        assert_eq!(types, vec!["bool", "int", "bool"]);
+    }
+
+    #[test]
+    fn rejects_mismatched_operands(){
+        let error = check_source("let a = 1\nlet b = a + true").unwrap_err();
+        assert_eq!(error.message, "Cannot apply '+' to int and bool");
+        assert_eq!(error.span, Span{ line: 2, column: 11, length: 1 });
+
+        let error = check_source("let a = 1 <= 2 < 3").unwrap_err();
+        assert_eq!(error.span, Span{ line: 1, column: 16, length: 1 });
+    }
+
+    #[test]
+    fn checks_annotations_and_reassignments(){
+        assert!(check_source("let a: bool = 1 == 1").is_ok());
+
+        let error = check_source("let a: bool = 1").unwrap_err();
+        assert_eq!(error.message, "Cannot assign int to variable 'a' of type bool");
+        assert_eq!(error.span, Span{ line: 1, column: 5, length: 1 });
+
+        let error = check_source("let a = 1\nif true { a = false }").unwrap_err();
+        assert_eq!(error.span, Span{ line: 2, column: 11, length: 1 });
+    }
+
+    #[test]
+    fn conditions_have_to_be_bools(){
+        let error = check_source("let a = 1\nwhile a + 1 { }").unwrap_err();
+        assert_eq!(error.message, "Condition has to be a bool, found int");
+        assert_eq!(error.span, Span{ line: 2, column: 7, length: 1 });
+    }
+
+    #[test]
+    fn vectors(){
+        assert!(check_source("let v = Vec2(1, 2) * Vec2(3, 4).x - Vec2(0, 1)\nlet x: float = -v.y").is_ok());
+
+        let error = check_source("let v = Vec2(1, 2) + 1").unwrap_err();
+        assert_eq!(error.message, "Cannot apply '+' to Vec2 and int");
+
+        let error = check_source("let v = Vec2(1)").unwrap_err();
+        assert_eq!(error.message, "Vec2 takes 2 arguments (x, y), got 1");
+
+        let error = check_source("let v = Vec2(1, true)").unwrap_err();
+        assert_eq!(error.message, "Argument 'y' of Vec2 has to be a number, found bool");
+
+        let error = check_source("let v = Vec2(1, 2)\nlet z = v.z").unwrap_err();
+        assert_eq!(error.message, "Vec2 has no field 'z'");
+        assert_eq!(error.span, Span{ line: 2, column: 11, length: 1 });
+    }
+
+    #[test]
+    fn int_float_promotion(){
+        let program = check_source("let a = 1 + 0.5\nlet b: float = 2\nlet c = 3 < 2.5\nlet d = Vec2(1.0, 2.0) * 2\nlet e = int(a)").unwrap();
+
+        let types = program.statements.iter().map(|stmt| match stmt{
+            Stmt::Assignment { variable, .. } => variable.typ.get().unwrap().name.clone(),
+            _ => panic!("Stmt is not an assignment"),
+        }).collect::<Vec<_>>();
+        assert_eq!(types, vec!["float", "float", "bool", "Vec2", "int"]);
+
+        let error = check_source("let a: int = 1.5").unwrap_err();
+        assert_eq!(error.message, "Cannot assign float to variable 'a' of type int");
+    }
+
+    #[test]
+    fn functions(){
+        assert!(check_source("fn f(a: float, v: Vec2) -> Vec2 { return v * a }\nlet v = f(2, Vec2(1.0, 0.0))").is_ok());
+
+        let error = check_source("let a = length(1.0)").unwrap_err();
+        assert_eq!(error.message, "Argument 'v' of length has to be Vec2, found float");
+        assert_eq!(error.span, Span{ line: 1, column: 9, length: 6 });
+
+        let error = check_source("let a = dot(Vec2(1.0, 0.0))").unwrap_err();
+        assert_eq!(error.message, "dot takes 2 arguments (a, b), got 1");
+
+        let error = check_source("fn f() -> int {\n    return true\n}").unwrap_err();
+        assert_eq!(error.message, "Function has to return int, found bool");
+        assert_eq!(error.span, Span{ line: 2, column: 5, length: 6 });
+
+        let error = check_source("fn f() { return 1 }").unwrap_err();
+        assert_eq!(error.message, "Function has to return void, found int");
+    }
+
+    #[test]
+    fn functions_return_on_every_path(){
+        assert!(check_source("fn f(a: int) -> int { if a > 0 { return 1 } else if a < 0 { return -1 } else { return 0 } }").is_ok());
+        assert!(check_source("fn f(a: int) -> int { if a > 0 { return 1 }\nreturn 0 }").is_ok());
+        assert!(check_source("fn f() { if false { return } }").is_ok());
+
+        let error = check_source("let a = 1\nfn f() -> int { if false { return 1 } }").unwrap_err();
+        assert_eq!(error.message, "Function 'f' doesn't return a int on every path");
+        assert_eq!(error.span, Span{ line: 2, column: 4, length: 1 });
+
+        let error = check_source("fn f() -> Vec2 { while true { return Vec2(0, 0) } }").unwrap_err();
+        assert_eq!(error.message, "Function 'f' doesn't return a Vec2 on every path");
+    }
+}
\ No newline at end of file
@@ -1,4 +1,6 @@
-use std::rc::Rc;
+use std::{fmt::Display, rc::Rc};
+
+use bevy::math::Vec2;
 
 #[derive(Debug)]
 pub struct Type{
@@ -13,6 +15,9 @@ pub fn base_types() -> Vec<Type>{
         Type{
             name: "float".to_owned()
         },
+        Type{
+            name: "bool".to_owned()
+        },
         Type{
             name: "Vec2".to_owned()
         },
@@ -22,14 +27,42 @@ pub fn base_types() -> Vec<Type>{
     ]
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Value{
     pub typ: Rc<Type>,
     pub data: ValueData
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ValueData{
     Int(i32),
     Float(f32),
+    Bool(bool),
+    Vec2(Vec2),
+    Void,
+}
+
+impl ValueData{
+    /// The name of the base type values of this kind have
+    pub fn type_name(&self) -> &'static str{
+        match self{
+            ValueData::Int(_) => "int",
+            ValueData::Float(_) => "float",
+            ValueData::Bool(_) => "bool",
+            ValueData::Vec2(_) => "Vec2",
+            ValueData::Void => "void",
+        }
+    }
+}
+
+impl Display for Value{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.data{
+            ValueData::Int(v) => write!(f, "{v}"),
+            ValueData::Float(v) => write!(f, "{v:?}"),
+            ValueData::Bool(v) => write!(f, "{v}"),
+            ValueData::Vec2(v) => write!(f, "Vec2({}, {})", v.x, v.y),
+            ValueData::Void => write!(f, "void"),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+
+use bevy::{ecs::{entity::Entity, event::{Event, EventWriter}, resource::Resource, system::{Commands, Query, Res, ResMut}}, math::Vec3, transform::components::Transform};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cli::{CaptureMode, SimulationSettings}, species::Species, FlockParams, ParticleComputationData};
+
+/// Hunters catch their prey when they get closer than this
+pub const CAPTURE_RADIUS: f32 = 5.0;
+
+/// The nearest neighbour a particle hunts
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Prey{
+    pub entity: Entity,
+    pub position: Vec3,
+    pub distance: f32,
+}
+
+/// Sent whenever a hunter catches its prey
+#[derive(Event, Clone, Copy, Debug, PartialEq)]
+pub struct Capture{
+    pub hunter: Entity,
+    pub prey: Entity,
+    /// The species of the prey before it was caught
+    pub species: Species,
+}
+
+/// How many particles of each species have been caught so far
+#[derive(Resource, Serialize, Deserialize, Default, Clone, Debug)]
+pub struct Captures{
+    caught: [usize; Species::ALL.len()],
+}
+
+impl Captures{
+    pub fn caught(&self, species: Species) -> usize{
+        self.caught[species.index()]
+    }
+}
+
+/// Hunters accelerate towards their prey and prey away from their hunters
+pub fn predation_force(transform: &Transform, data: &ParticleComputationData, params: &FlockParams) -> Vec3{
+    let hunt = data.prey.map(|prey| (prey.position - transform.translation).normalize_or_zero()).unwrap_or_default();
+
+    (params.hunt * hunt + params.flee * data.flee_dir) * params.acceleration
+}
+
+/// Lets every hunter that got close enough to its prey catch it.
+/// A particle can only be caught once, even if several hunters reach it at the same time.
+pub fn capture_prey(
+    mut commands: Commands,
+    particles: Query<(Entity, &Species, &ParticleComputationData)>,
+    settings: Res<SimulationSettings>,
+    base_params: Res<FlockParams>,
+    mut captures: ResMut<Captures>,
+    mut events: EventWriter<Capture>,
+){
+    let mut caught = HashSet::new();
+    for (hunter, &hunter_species, data) in &particles{
+        let Some(prey) = data.prey else { continue };
+        if prey.distance > CAPTURE_RADIUS || !caught.insert(prey.entity){
+            continue;
+        }
+        let Ok((_, &prey_species, _)) = particles.get(prey.entity) else { continue };
+
+        match settings.capture{
+            CaptureMode::Despawn => commands.entity(prey.entity).despawn(),
+            CaptureMode::Convert => {
+                commands.entity(prey.entity).insert((hunter_species, hunter_species.flock_params(*base_params)));
+            },
+        }
+
+        captures.caught[prey_species.index()] += 1;
+        events.write(Capture{ hunter, prey: prey.entity, species: prey_species });
+    }
+}
\ No newline at end of file
@@ -0,0 +1,221 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+
+use bevy::{ecs::{entity::Entity, query::With, resource::Resource, system::{Commands, Query, Res, ResMut}}, transform::components::Transform};
+use serde::Serialize;
+
+use crate::{cli::SimulationSettings, species::Species, FlockParams, Particle, Tick, Velocity};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RecordFormat{
+    /// Comma separated values, with the description of the run in comments starting with `#`
+    Csv,
+    /// One JSON object per line, the first one describing the run
+    JsonLines,
+}
+
+impl RecordFormat{
+    fn from_path(path: &Path) -> Option<Self>{
+        match path.extension()?.to_str()?{
+            "csv" => Some(Self::Csv),
+            "jsonl" => Some(Self::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+/// Everything needed to repeat a recorded run, written before the trajectories
+#[derive(Serialize)]
+pub struct RunHeader<'a>{
+    pub seed: u64,
+    pub settings: &'a SimulationSettings,
+    pub world_size: [f32; 2],
+    /// The flocking parameters set by the script, before species change them
+    pub params: &'a FlockParams,
+}
+
+/// Where a particle was at a tick
+#[derive(Serialize)]
+struct Row{
+    tick: u64,
+    entity: u64,
+    species: Species,
+    x: f32,
+    y: f32,
+    velocity_x: f32,
+    velocity_y: f32,
+}
+
+/// Writes the positions and velocities of all particles every `every` ticks.
+/// Resumed runs go on counting the ticks where the snapshot left off.
+#[derive(Resource)]
+pub struct Recorder{
+    writer: Box<dyn Write + Send + Sync>,
+    format: RecordFormat,
+    every: u64,
+}
+
+impl Recorder{
+    /// The format depends on the extension of `path`, `.csv` or `.jsonl`
+    pub fn create(path: &Path, every: u64, header: &RunHeader) -> io::Result<Self>{
+        let format = RecordFormat::from_path(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "recordings have to end in .csv or .jsonl"))?;
+        let mut recorder = Self { writer: Box::new(BufWriter::new(File::create(path)?)), format, every };
+        recorder.write_header(header)?;
+        Ok(recorder)
+    }
+
+    fn write_header(&mut self, header: &RunHeader) -> io::Result<()>{
+        match self.format{
+            RecordFormat::Csv => {
+                // Serialized one by one, as converting the whole header into a JSON value would sort the fields and widen the floats
+                let fields = [
+                    ("seed", serde_json::to_string(&header.seed)?),
+                    ("settings", serde_json::to_string(header.settings)?),
+                    ("world_size", serde_json::to_string(&header.world_size)?),
+                    ("params", serde_json::to_string(header.params)?),
+                ];
+                for (name, value) in fields{
+                    writeln!(self.writer, "# {name} = {value}")?;
+                }
+                writeln!(self.writer, "tick,entity,species,x,y,velocity_x,velocity_y")?;
+            },
+            RecordFormat::JsonLines => {
+                serde_json::to_writer(&mut self.writer, header)?;
+                writeln!(self.writer)?;
+            },
+        }
+        self.writer.flush()
+    }
+
+    fn write_row(&mut self, row: &Row) -> io::Result<()>{
+        match self.format{
+            RecordFormat::Csv => writeln!(
+                self.writer, "{},{},{},{},{},{},{}",
+                row.tick, row.entity, row.species.name(), row.x, row.y, row.velocity_x, row.velocity_y,
+            ),
+            RecordFormat::JsonLines => {
+                serde_json::to_writer(&mut self.writer, row)?;
+                writeln!(self.writer)
+            },
+        }
+    }
+}
+
+/// Runs after every tick. A recording that fails stops, but the simulation goes on.
+pub fn record_trajectories(
+    mut commands: Commands,
+    mut recorder: ResMut<Recorder>,
+    tick: Res<Tick>,
+    particles: Query<(Entity, &Species, &Transform, &Velocity), With<Particle>>,
+){
+    let Tick(tick) = *tick;
+    if !tick.is_multiple_of(recorder.every){
+        return;
+    }
+
+    let result = particles.iter().try_for_each(|(entity, species, transform, velocity)|{
+        recorder.write_row(&Row{
+            tick,
+            entity: entity.to_bits(),
+            species: *species,
+            x: transform.translation.x,
+            y: transform.translation.y,
+            velocity_x: velocity.0.x,
+            velocity_y: velocity.0.y,
+        })
+    });
+
+    // Flushing every time keeps the file readable while the simulation is still running
+    if let Err(error) = result.and_then(|()| recorder.writer.flush()){
+        eprintln!("Failed to record the trajectories: {error}\nThe recording is stopped");
+        commands.remove_resource::<Recorder>();
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use std::{fs, path::Path};
+
+    use bevy::{ecs::{schedule::{IntoScheduleConfigs, Schedule}, world::World}, math::Vec3, transform::components::Transform};
+
+    use crate::{bounds::BoundsMode, cli::SimulationSettings, recorder::{record_trajectories, Recorder, RunHeader}, species::Species, FlockParams, testing::temp_path, count_ticks, Particle, Tick, Velocity};
+
+    fn settings(path: &Path) -> SimulationSettings{
+        SimulationSettings{
+            particles: 2,
+            predators: 0,
+            seed: Some(5),
+            bounds: BoundsMode::Wrap,
+            record: Some(path.to_owned()),
+            record_every: 2,
+            ..Default::default()
+        }
+    }
+
+    /// Records two particles for five ticks and returns the lines of the recording
+    fn record(path: &Path) -> Vec<String>{
+        let settings = settings(path);
+        let header = RunHeader{ seed: 5, settings: &settings, world_size: [1280.0, 720.0], params: &FlockParams::default() };
+
+        let mut world = World::new();
+        world.insert_resource(Recorder::create(path, 2, &header).unwrap());
+        world.init_resource::<Tick>();
+        world.spawn((Particle{}, Species::Red, Transform::from_xyz(1.0, 2.0, 1.0), Velocity(Vec3::new(3.0, 4.0, 0.0))));
+        world.spawn((Particle{}, Species::Predator, Transform::from_xyz(-1.5, 0.0, 1.0), Velocity(Vec3::ZERO)));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((count_ticks, record_trajectories).chain());
+        for _ in 0..5{
+            schedule.run(&mut world);
+        }
+        drop(world);
+
+        let recording = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        recording.lines().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn record_csv(){
+        let lines = record(&temp_path("recording.csv"));
+
+        assert_eq!(lines[0], "# seed = 5");
+        assert!(lines[1].starts_with("# settings = {\"particles\":2,") && lines[1].contains("\"bounds\":\"wrap\""));
+        assert_eq!(lines[2], "# world_size = [1280.0,720.0]");
+        assert!(lines[3].starts_with("# params = {\"perception_radius\":75.0,\"separation_radius\":20.0,\"cohesion\":0.3,"));
+        assert_eq!(lines[4], "tick,entity,species,x,y,velocity_x,velocity_y");
+
+        // Ticks 2 and 4, two particles each
+        let rows = lines[5..].iter().map(|line| line.split(',').collect::<Vec<_>>()).collect::<Vec<_>>();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows.iter().map(|row| row[0]).collect::<Vec<_>>(), ["2", "2", "4", "4"]);
+        assert_eq!(rows[0][2..], ["red", "1", "2", "3", "4"]);
+        assert_eq!(rows[1][2..], ["predator", "-1.5", "0", "0", "0"]);
+        assert_eq!(rows[0][1], rows[2][1]);
+    }
+
+    #[test]
+    fn record_json_lines(){
+        let lines = record(&temp_path("recording.jsonl"));
+        let objects = lines.iter().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()).collect::<Vec<_>>();
+
+        assert_eq!(objects[0]["seed"], 5);
+        assert_eq!(objects[0]["settings"]["record_every"], 2);
+        assert_eq!(objects[0]["world_size"], serde_json::json!([1280.0, 720.0]));
+
+        assert_eq!(objects.len(), 5);
+        assert_eq!(objects[1]["tick"], 2);
+        assert_eq!(objects[1]["species"], "red");
+        assert_eq!(objects[1]["velocity_y"], 4.0);
+        assert_eq!(objects[4]["tick"], 4);
+        assert_eq!(objects[4]["species"], "predator");
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected(){
+        let path = temp_path("recording.txt");
+        let settings = settings(&path);
+        let header = RunHeader{ seed: 5, settings: &settings, world_size: [1280.0, 720.0], params: &FlockParams::default() };
+        let error = Recorder::create(&path, 2, &header).err().unwrap();
+        assert_eq!(error.to_string(), "recordings have to end in .csv or .jsonl");
+    }
+}
\ No newline at end of file
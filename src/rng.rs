@@ -0,0 +1,61 @@
+use bevy::ecs::resource::Resource;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// The only source of randomness in the simulation, so that runs with the same seed are identical.
+/// ChaCha8 produces the same numbers on every platform and with every version of rand.
+/// Snapshots store its state, so that resumed runs continue with the same numbers.
+#[derive(Resource, Serialize, Deserialize, Clone)]
+pub struct SimulationRng{
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl SimulationRng{
+    /// Picks a random seed if none is given
+    pub fn new(seed: Option<u64>) -> Self{
+        let seed = seed.unwrap_or_else(|| rand::rng().random());
+        Self { seed, rng: ChaCha8Rng::seed_from_u64(seed) }
+    }
+
+    /// The seed to pass to `--seed` to repeat this run
+    pub fn seed(&self) -> u64{
+        self.seed
+    }
+}
+
+impl RngCore for SimulationRng{
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        self.rng.fill_bytes(dst)
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use rand::Rng;
+
+    use crate::rng::SimulationRng;
+
+    #[test]
+    fn same_seed_same_numbers(){
+        let numbers = |rng: &mut SimulationRng| (0..10).map(|_| rng.random::<u32>()).collect::<Vec<_>>();
+
+        let (mut a, mut b) = (SimulationRng::new(Some(3)), SimulationRng::new(Some(3)));
+        assert_eq!(numbers(&mut a), numbers(&mut b));
+        assert_ne!(numbers(&mut a), numbers(&mut SimulationRng::new(Some(4))));
+
+        // Runs with a random seed can be repeated with the seed they report
+        let mut random = SimulationRng::new(None);
+        let seed = random.seed();
+        assert_eq!(numbers(&mut random), numbers(&mut SimulationRng::new(Some(seed))));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,404 @@
+use std::rc::Rc;
+#[cfg(feature = "gui")]
+use std::{fs, io::{self, IsTerminal}, path::{Path, PathBuf}, time::SystemTime};
+
+use bevy::math::Vec2;
+#[cfg(feature = "gui")]
+use bevy::{ecs::{resource::Resource, system::{NonSendMut, Query, Res, ResMut}}, time::{Time, Timer, TimerMode}};
+use itertools::Itertools;
+
+use crate::{particlescript::{diagnostics::SourceError, interpreter::{Environment, Interpreter}, lexer::Lexer, parser::{self, Function, Scope}, typechecker, types::{Value, ValueData}}, FlockParams};
+#[cfg(feature = "gui")]
+use crate::{bounds::WorldBounds, species::Species};
+
+/// Reads and runs the particle script.
+/// Falls back to an empty script if it can't be run, so that it can be fixed while the simulation runs.
+#[cfg(feature = "gui")]
+pub fn load_script(path: &Path) -> io::Result<ParticleScript>{
+    // The whole source is kept around to be able to show the offending lines in error messages
+    let source = fs::read_to_string(path)?;
+    let colored = std::io::stderr().is_terminal();
+
+    Ok(match ParticleScript::new(&source, colored){
+        Ok(script) => {
+            script.print_variables();
+            script
+        },
+        Err(error) => {
+            eprintln!("{error}");
+            ParticleScript::default()
+        }
+    })
+}
+
+/// Remembers when the script file was last changed, so that it can be reloaded
+/// while the simulation is running.
+#[cfg(feature = "gui")]
+#[derive(Resource)]
+pub struct ScriptWatcher{
+    path: PathBuf,
+    last_modified: Option<SystemTime>,
+    /// The file is only checked every now and then
+    timer: Timer,
+}
+
+#[cfg(feature = "gui")]
+impl ScriptWatcher{
+    pub fn new(path: impl Into<PathBuf>) -> Self{
+        let path = path.into();
+        let last_modified = modified(&path);
+        Self { path, last_modified, timer: Timer::from_seconds(0.5, TimerMode::Repeating) }
+    }
+
+    /// Returns the new source of the script if the file changed since the last poll
+    fn poll(&mut self) -> Option<String>{
+        let modified = modified(&self.path);
+        if modified == self.last_modified{
+            return None;
+        }
+        self.last_modified = modified;
+
+        fs::read_to_string(&self.path).inspect_err(|error|{
+            eprintln!("Failed to read {}: {error}", self.path.display());
+        }).ok()
+    }
+}
+
+#[cfg(feature = "gui")]
+fn modified(path: &Path) -> Option<SystemTime>{
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reloads the script when its file changes. If the new version fails, its diagnostic
+/// is shown and the simulation keeps running with the previous version.
+#[cfg(feature = "gui")]
+pub fn reload_script(
+    time: Res<Time>,
+    mut watcher: ResMut<ScriptWatcher>,
+    mut script: NonSendMut<ParticleScript>,
+    mut params: ResMut<FlockParams>,
+    mut particles: Query<(&Species, &mut FlockParams)>,
+    bounds: Res<WorldBounds>,
+){
+    if !watcher.timer.tick(time.delta()).just_finished(){
+        return;
+    }
+    let Some(source) = watcher.poll() else { return };
+
+    match script.reload(&source, std::io::stderr().is_terminal(), &bounds){
+        Ok(()) => {
+            *params = script.params;
+            for (species, mut particle_params) in &mut particles{
+                *particle_params = species.flock_params(script.params);
+            }
+            println!("Reloaded {}", watcher.path.display());
+        },
+        Err(error) => eprintln!("{error}\nKeeping the previous version of {}", watcher.path.display()),
+    }
+}
+
+/// A particle script that has been run. The interpreter is kept around,
+/// so that the simulation can call the functions the script declares.
+pub struct ParticleScript{
+    interpreter: Interpreter,
+    /// The steering rule of the script, if it declares one
+    steer: Option<Rc<Function>>,
+    /// The flocking parameters the script sets for all particles
+    pub params: FlockParams,
+}
+
+impl Default for ParticleScript{
+    fn default() -> Self {
+        Self { interpreter: Interpreter::new(&Scope::root()), steer: None, params: FlockParams::default() }
+    }
+}
+
+impl ParticleScript{
+    /// Lexes, parses, type checks and runs a script.
+    /// Errors are returned already rendered as diagnostics.
+    pub fn new(source: &str, colored: bool) -> Result<Self, String>{
+        let mut lexer = Lexer::new(source.chars());
+
+        let mut scope = Scope::root();
+        let program = parser::parse_program(&mut lexer.by_ref().multipeek(), &mut scope);
+
+        // A lexer error ends the token stream early, so it is the actual cause of any parse error
+        if let Some(error) = &lexer.error{
+            return Err(error.diagnostic(source).colored(colored).to_string());
+        }
+        let program = program.map_err(|error| error.diagnostic(source).colored(colored).to_string())?;
+
+        typechecker::check(&program, &scope).map_err(|error| error.diagnostic(source).colored(colored).to_string())?;
+
+        let mut interpreter = Interpreter::new(&scope);
+        interpreter.run(&program).map_err(|error| error.to_string())?;
+
+        let params = FlockParams::from_environment(&interpreter.environment)?;
+
+        let steer = scope.find_function("steer");
+        if let Some(steer) = &steer{
+            // Rules written before species existed don't take `species_dir`
+            let parameter_count = steer.parameters.len();
+            let takes_vectors = (parameter_count == STEER_PARAMETERS.len() || parameter_count == STEER_PARAMETERS.len() - 1)
+                && steer.parameters.iter().all(|p| p.typ.get().is_some_and(|t| t.name == "Vec2"));
+            if !takes_vectors || steer.return_type.name != "Vec2"{
+                let (optional, required) = STEER_PARAMETERS.split_last().unwrap();
+                return Err(format!(
+                    "'steer' has to take {} or {} Vec2 parameters ({}[, {}]) and return a Vec2",
+                    required.len(), STEER_PARAMETERS.len(), required.join(", "), optional
+                ));
+            }
+        }
+
+        Ok(Self { interpreter, steer, params })
+    }
+
+    pub fn print_variables(&self){
+        for (variable, value) in self.interpreter.environment.variables(){
+            println!("{}: {} = {}", variable.name, value.typ.name, value);
+        }
+    }
+
+    /// Replaces this script with a new version. Keeps the current one if the new one fails
+    /// or lets particles see too far for the world they live in.
+    #[cfg(feature = "gui")]
+    pub fn reload(&mut self, source: &str, colored: bool, bounds: &WorldBounds) -> Result<(), String>{
+        let script = Self::new(source, colored)?;
+        bounds.check(Species::max_perception_radius(script.params))?;
+        *self = script;
+        Ok(())
+    }
+
+    pub fn has_steering(&self) -> bool{
+        self.steer.is_some()
+    }
+
+    /// Calls the `steer` function of the script and returns the acceleration it chose.
+    /// Returns `None` if the script doesn't declare a steering rule. A rule that fails
+    /// at runtime is reported once and then disabled.
+    pub fn steer(&mut self, input: SteeringInput) -> Option<Vec2>{
+        let steer = self.steer.clone()?;
+
+        input.params.write_to(&mut self.interpreter.environment);
+        let arguments = input.arguments()[..steer.parameters.len()].iter().map(|v| self.interpreter.value(ValueData::Vec2(*v))).collect();
+        match self.interpreter.call(&steer, arguments){
+            Ok(Value{ data: ValueData::Vec2(acceleration), .. }) => Some(acceleration),
+            Ok(value) => unreachable!("'steer' returned {} although it was checked to return Vec2", value.typ.name),
+            Err(error) => {
+                eprintln!("{error}\nThe builtin steering rule is used instead");
+                self.steer = None;
+                None
+            }
+        }
+    }
+}
+
+/// The order in which `steer` receives the fields of `SteeringInput`. The last one is optional.
+const STEER_PARAMETERS: [&str; 6] = ["position", "velocity", "center", "heading", "avoidance_dir", "species_dir"];
+
+/// Everything a steering rule knows about a particle and its neighbourhood
+pub struct SteeringInput{
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub center: Vec2,
+    pub heading: Vec2,
+    pub avoidance_dir: Vec2,
+    /// Where the reactions to other species pull the particle
+    pub species_dir: Vec2,
+    /// The parameters of the particle. The flocking parameter variables of the script hold them while it is steered.
+    pub params: FlockParams,
+}
+
+impl SteeringInput{
+    fn arguments(&self) -> [Vec2; 6]{
+        [self.position, self.velocity, self.center, self.heading, self.avoidance_dir, self.species_dir]
+    }
+}
+
+impl FlockParams{
+    /// The names of the script variables holding the parameters
+    fn variables(&mut self) -> [(&'static str, &mut f32); 13]{
+        [
+            ("perception_radius", &mut self.perception_radius),
+            ("separation_radius", &mut self.separation_radius),
+            ("cohesion_weight", &mut self.cohesion),
+            ("alignment_weight", &mut self.alignment),
+            ("avoidance_weight", &mut self.avoidance),
+            ("center_weight", &mut self.center),
+            ("other_species_weight", &mut self.other_species),
+            ("hunt_weight", &mut self.hunt),
+            ("flee_weight", &mut self.flee),
+            ("obstacle_weight", &mut self.obstacle),
+            ("wall_weight", &mut self.wall),
+            ("acceleration", &mut self.acceleration),
+            ("max_speed", &mut self.max_speed),
+        ]
+    }
+
+    /// Reads the parameters from the variables of an executed script.
+    /// Parameters the script doesn't assign keep their default value.
+    fn from_environment(environment: &Environment) -> Result<Self, String>{
+        let mut params = Self::default();
+        for (name, weight) in params.variables(){
+            match environment.find(name){
+                None => {},
+                Some(Value{ data: ValueData::Float(v), .. }) => *weight = *v,
+                // Every particle puts its own parameters into these variables, which don't have to be whole numbers
+                Some(value) => return Err(format!("Flocking parameter '{name}' has to be a float, found {}", value.typ.name)),
+            }
+        }
+        Ok(params)
+    }
+
+    /// Puts the parameters into the variables of an executed script.
+    /// Variables the script doesn't assign stay unassigned.
+    fn write_to(mut self, environment: &mut Environment){
+        for (name, weight) in self.variables(){
+            if let Some(ValueData::Float(v)) = environment.find_mut(name).map(|value| &mut value.data){
+                *v = *weight;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test{
+    #[cfg(feature = "gui")]
+    use std::{fs, time::Duration};
+
+    use bevy::math::Vec2;
+
+    use crate::{script::{ParticleScript, SteeringInput}, FlockParams};
+    #[cfg(feature = "gui")]
+    use crate::{bounds::{BoundsMode, WorldBounds}, script::ScriptWatcher, testing::temp_path};
+
+    fn input() -> SteeringInput{
+        SteeringInput{
+            position: Vec2::new(10.0, 0.0),
+            velocity: Vec2::new(0.0, 1.0),
+            center: Vec2::new(10.0, 5.0),
+            heading: Vec2::new(1.0, 0.0),
+            avoidance_dir: Vec2::ZERO,
+            species_dir: Vec2::ZERO,
+            params: FlockParams::default(),
+        }
+    }
+
+    #[test]
+    fn flock_params_from_script(){
+        let script = ParticleScript::new("let cohesion_weight = 0.5\nlet max_speed: float = 50\nlet perception_radius = 60.0\nlet unrelated = true", false).unwrap();
+        assert_eq!(script.params, FlockParams{ cohesion: 0.5, max_speed: 50.0, perception_radius: 60.0, ..Default::default() });
+
+        // The latest declaration of a shadowed parameter wins
+        let script = ParticleScript::new("let max_speed = 10.0\nlet max_speed = 200.0", false).unwrap();
+        assert_eq!(script.params.max_speed, 200.0);
+
+        let error = ParticleScript::new("let alignment_weight = Vec2(1.0, 0.0)", false).err().unwrap();
+        assert_eq!(error, "Flocking parameter 'alignment_weight' has to be a float, found Vec2");
+        let error = ParticleScript::new("let max_speed = 50", false).err().unwrap();
+        assert_eq!(error, "Flocking parameter 'max_speed' has to be a float, found int");
+    }
+
+    #[test]
+    fn steering_function(){
+        let mut script = ParticleScript::new("
+            let strength = 2
+            fn steer(position: Vec2, velocity: Vec2, center: Vec2, heading: Vec2, avoidance_dir: Vec2, species_dir: Vec2) -> Vec2 {
+                return normalize(center - position) * strength + heading
+            }
+        ", false).unwrap();
+        assert_eq!(script.steer(input()), Some(Vec2::new(1.0, 2.0)));
+
+        // The parameter variables hold the parameters of the particle that is steered
+        let mut script = ParticleScript::new("
+            let acceleration = 1.0
+            let acceleration = 5.0
+            let max_speed = 100.0
+            fn steer(position: Vec2, velocity: Vec2, center: Vec2, heading: Vec2, avoidance_dir: Vec2, species_dir: Vec2) -> Vec2 {
+                return Vec2(acceleration, max_speed)
+            }
+        ", false).unwrap();
+        let params = FlockParams{ acceleration: 6.25, max_speed: 125.0, ..Default::default() };
+        assert_eq!(script.steer(SteeringInput{ params, ..input() }), Some(Vec2::new(6.25, 125.0)));
+        assert_eq!(script.steer(input()), Some(Vec2::new(5.0, 100.0)));
+
+        // Rules written before species existed don't receive where the other species pull
+        let mut script = ParticleScript::new("
+            fn steer(position: Vec2, velocity: Vec2, center: Vec2, heading: Vec2, avoidance_dir: Vec2) -> Vec2 {
+                return avoidance_dir + velocity
+            }
+        ", false).unwrap();
+        assert_eq!(script.steer(SteeringInput{ species_dir: Vec2::ONE, ..input() }), Some(Vec2::new(0.0, 1.0)));
+
+        // Scripts without a steering rule leave steering to the builtin one
+        let mut script = ParticleScript::new("let cohesion_weight = 0.5", false).unwrap();
+        assert_eq!(script.steer(input()), None);
+
+        let error = ParticleScript::new("fn steer(position: Vec2) -> Vec2 { return position }", false).err().unwrap();
+        assert_eq!(error, "'steer' has to take 5 or 6 Vec2 parameters (position, velocity, center, heading, avoidance_dir[, species_dir]) and return a Vec2");
+    }
+
+    #[test]
+    fn failing_steering_function_is_disabled(){
+        let mut script = ParticleScript::new("
+            fn steer(position: Vec2, velocity: Vec2, center: Vec2, heading: Vec2, avoidance_dir: Vec2, species_dir: Vec2) -> Vec2 {
+                if position.x > 0.0 {
+                    let overflow = 2147483647 + 1
+                }
+                return position
+            }
+        ", false).unwrap();
+        assert_eq!(script.steer(input()), None);
+        assert!(script.steer.is_none());
+    }
+
+    #[test]
+    fn example_scripts_run(){
+        // The default script leaves steering to the builtin rule, which can run in parallel
+        let script = ParticleScript::new(include_str!("../first.pts"), false).unwrap();
+        assert_eq!(script.params, FlockParams::default());
+        assert!(!script.has_steering());
+
+        let mut script = ParticleScript::new(include_str!("../steering.pts"), false).unwrap();
+        assert_eq!(script.params, FlockParams::default());
+        assert!(script.steer(input()).is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn failed_reload_keeps_previous_version(){
+        let mut script = ParticleScript::new("let max_speed = 50.0", false).unwrap();
+
+        let bounds = WorldBounds{ mode: BoundsMode::Wrap, size: Vec2::new(400.0, 300.0) };
+        let error = script.reload("let max_speed = ", false, &bounds).unwrap_err();
+        assert!(error.starts_with("error: Unexpected end of file"));
+        assert_eq!(script.params.max_speed, 50.0);
+
+        // Particles would see the same neighbours on both sides of the wrapping world
+        let error = script.reload("let max_speed = 70.0\nlet perception_radius = 150.0", false, &bounds).unwrap_err();
+        assert!(error.starts_with("With wrap-around, the world has to be larger than twice the perception radius of 150"));
+        assert_eq!(script.params.max_speed, 50.0);
+
+        script.reload("let max_speed = 60.0", false, &bounds).unwrap();
+        assert_eq!(script.params.max_speed, 60.0);
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn watcher_notices_changes(){
+        let path = temp_path("watcher.pts");
+        fs::write(&path, "let max_speed = 50").unwrap();
+
+        let mut watcher = ScriptWatcher::new(&path);
+        assert_eq!(watcher.poll(), None);
+
+        // Setting the time explicitly doesn't depend on the resolution of the file system clock
+        fs::write(&path, "let max_speed = 60").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(watcher.last_modified.unwrap() + Duration::from_secs(1)).unwrap();
+
+        assert_eq!(watcher.poll().as_deref(), Some("let max_speed = 60"));
+        assert_eq!(watcher.poll(), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
\ No newline at end of file
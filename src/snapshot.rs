@@ -0,0 +1,217 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use bevy::{ecs::{entity::Entity, query::With, world::World}, math::Vec3, transform::components::Transform};
+#[cfg(feature = "gui")]
+use bevy::input::{keyboard::KeyCode, ButtonInput};
+use serde::{Deserialize, Serialize};
+
+use crate::{bounds::WorldBounds, cli::SimulationSettings, obstacles::Obstacle, predation::{Captures, Prey}, rng::SimulationRng, species::Species, FlockParams, Particle, ParticleComputationData, Tick, Velocity};
+
+/// Increased whenever the layout of snapshots changes. Snapshots of other versions are rejected.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// Everything about a particle. Its look follows from its species.
+#[derive(Serialize, Deserialize)]
+struct ParticleSnapshot{
+    /// The entity of the particle when the snapshot was taken, to find the prey of hunters again
+    entity: Entity,
+    species: Species,
+    translation: Vec3,
+    scale: Vec3,
+    velocity: Vec3,
+    params: FlockParams,
+    data: ParticleComputationData,
+}
+
+/// The whole state of a simulation between two ticks
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot{
+    version: u32,
+    pub settings: SimulationSettings,
+    pub bounds: WorldBounds,
+    /// The flocking parameters of the script. Every particle also has its own.
+    pub params: FlockParams,
+    tick: Tick,
+    pub rng: SimulationRng,
+    captures: Captures,
+    /// In the order the simulation updates them, which decides the order of floating point operations
+    particles: Vec<ParticleSnapshot>,
+    obstacles: Vec<Obstacle>,
+}
+
+/// Just enough of a snapshot to tell whether the rest can be read
+#[derive(Deserialize)]
+struct Version{
+    version: u32,
+}
+
+impl Snapshot{
+    pub fn capture(world: &mut World) -> Self{
+        let mut particles = world.query_filtered::<(Entity, &Species, &Transform, &Velocity, &FlockParams, &ParticleComputationData), With<Particle>>();
+        let particles = particles.iter(world).map(|(entity, species, transform, velocity, params, data)| ParticleSnapshot{
+            entity,
+            species: *species,
+            translation: transform.translation,
+            scale: transform.scale,
+            velocity: velocity.0,
+            params: *params,
+            data: *data,
+        }).collect();
+        let obstacles = world.query::<&Obstacle>().iter(world).cloned().collect();
+
+        Self{
+            version: SNAPSHOT_VERSION,
+            settings: world.resource::<SimulationSettings>().clone(),
+            bounds: *world.resource::<WorldBounds>(),
+            params: *world.resource::<FlockParams>(),
+            tick: *world.resource::<Tick>(),
+            rng: world.resource::<SimulationRng>().clone(),
+            captures: world.resource::<Captures>().clone(),
+            particles,
+            obstacles,
+        }
+    }
+
+    /// Spawns the particles and obstacles of the snapshot and restores the random numbers,
+    /// the parameters, the tick and the captures. The settings and bounds are left to the caller,
+    /// as they are needed before the simulation is set up.
+    pub fn restore(self, world: &mut World){
+        world.insert_resource(self.params);
+        world.insert_resource(self.tick);
+        world.insert_resource(self.rng);
+        world.insert_resource(self.captures);
+
+        // The entities are reserved first, so that hunters can refer to prey spawned after them
+        let entities = self.particles.iter().map(|particle| (particle.entity, world.spawn_empty().id())).collect::<HashMap<_, _>>();
+        for particle in self.particles{
+            let prey = particle.data.prey.and_then(|prey| Some(Prey{ entity: *entities.get(&prey.entity)?, ..prey }));
+            world.entity_mut(entities[&particle.entity]).insert((
+                Particle{},
+                particle.species,
+                Velocity(particle.velocity),
+                particle.params,
+                ParticleComputationData{ prey, ..particle.data },
+                Transform::from_translation(particle.translation).with_scale(particle.scale),
+            ));
+        }
+
+        world.spawn_batch(self.obstacles);
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()>{
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String>{
+        let json = fs::read_to_string(path).map_err(|error| format!("Failed to read {}: {error}", path.display()))?;
+        Self::from_json(&json).map_err(|error| format!("Failed to read the snapshot {}: {error}", path.display()))
+    }
+
+    fn from_json(json: &str) -> Result<Self, String>{
+        let Version{ version } = serde_json::from_str(json).map_err(|error| error.to_string())?;
+        if version != SNAPSHOT_VERSION{
+            return Err(format!("it has version {version}, but only version {SNAPSHOT_VERSION} is supported"));
+        }
+        serde_json::from_str(json).map_err(|error| error.to_string())
+    }
+}
+
+/// Saves a snapshot to the path given with `--snapshot` when F5 is pressed
+#[cfg(feature = "gui")]
+pub fn save_on_key(world: &mut World){
+    if !world.resource::<ButtonInput<KeyCode>>().just_pressed(KeyCode::F5){
+        return;
+    }
+    let Some(path) = world.resource::<SimulationSettings>().snapshot.clone() else {
+        eprintln!("Pass --snapshot to choose where to save snapshots to");
+        return;
+    };
+
+    match Snapshot::capture(world).save(&path){
+        Ok(()) => println!("Saved a snapshot to {}", path.display()),
+        Err(error) => eprintln!("Failed to save a snapshot to {}: {error}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use std::collections::BTreeSet;
+
+    use bevy::{app::App, ecs::query::With, transform::components::Transform};
+    use rand::Rng;
+
+    use crate::{bounds::{BoundsMode, WorldBounds}, cli::{CaptureMode, SimulationSettings, DEFAULT_WINDOW_SIZE}, headless::simulate, predation::Captures, rng::SimulationRng, snapshot::Snapshot, species::Species, testing::{temp_path, test_app}, Particle};
+
+    fn settings() -> SimulationSettings{
+        SimulationSettings{
+            particles: 80,
+            capture: CaptureMode::Convert,
+            seed: Some(11),
+            bounds: BoundsMode::Wrap,
+            ..Default::default()
+        }
+    }
+
+    /// The exact positions of all particles, how many were caught and the next random number
+    fn state(app: &mut App) -> (Vec<[u32; 3]>, [usize; 3], u64){
+        let world = app.world_mut();
+        let positions = world.query_filtered::<&Transform, With<Particle>>().iter(world).map(|transform| transform.translation.to_array().map(f32::to_bits)).collect();
+        let captures = world.resource::<Captures>();
+        let caught = Species::ALL.map(|species| captures.caught(species));
+        (positions, caught, world.resource_mut::<SimulationRng>().random())
+    }
+
+    #[test]
+    fn resumed_run_matches_uninterrupted_run(){
+        let mut uninterrupted = test_app(settings());
+        simulate(&mut uninterrupted, 400);
+
+        let path = temp_path("snapshot.json");
+        let mut interrupted = test_app(settings());
+        simulate(&mut interrupted, 200);
+        Snapshot::capture(interrupted.world_mut()).save(&path).unwrap();
+
+        // Settings stored in the snapshot win over the ones given when resuming
+        let mut resumed = test_app(SimulationSettings{ resume: Some(path.clone()), particles: 5, bounds: BoundsMode::None, ..settings() });
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(*resumed.world().resource::<WorldBounds>(), WorldBounds{ mode: BoundsMode::Wrap, size: DEFAULT_WINDOW_SIZE });
+        simulate(&mut resumed, 200);
+
+        let expected = state(&mut uninterrupted);
+        // Otherwise the test doesn't cover particles that changed their species
+        assert!(expected.1.iter().sum::<usize>() > 0);
+        assert_eq!(state(&mut resumed), expected);
+    }
+
+    #[test]
+    fn resumed_recording_continues_the_run(){
+        let snapshot_path = temp_path("recorded_snapshot.json");
+        let mut interrupted = test_app(settings());
+        simulate(&mut interrupted, 30);
+        let mut snapshot = Snapshot::capture(interrupted.world_mut());
+        snapshot.params.max_speed = 42.0;
+        snapshot.save(&snapshot_path).unwrap();
+
+        let recording_path = temp_path("resumed.csv");
+        let mut resumed = test_app(SimulationSettings{ resume: Some(snapshot_path.clone()), record: Some(recording_path.clone()), record_every: 10, ..settings() });
+        std::fs::remove_file(&snapshot_path).unwrap();
+        simulate(&mut resumed, 20);
+        drop(resumed);
+
+        let recording = std::fs::read_to_string(&recording_path).unwrap();
+        std::fs::remove_file(&recording_path).unwrap();
+        // The header describes the parameters the run goes on with, not the ones of the script
+        assert!(recording.lines().any(|line| line.starts_with("# params = ") && line.contains("\"max_speed\":42.0")));
+        let ticks = recording.lines().filter(|line| !line.starts_with('#')).skip(1).map(|row| row.split(',').next().unwrap()).collect::<BTreeSet<_>>();
+        assert_eq!(ticks, BTreeSet::from(["40", "50"]));
+    }
+
+    #[test]
+    fn other_versions_are_rejected(){
+        let json = serde_json::to_string(&Snapshot::capture(test_app(settings()).world_mut())).unwrap();
+        let json = json.replacen("\"version\":2", "\"version\":1", 1);
+        assert_eq!(Snapshot::from_json(&json).err().unwrap(), "it has version 1, but only version 2 is supported");
+
+        assert!(Snapshot::from_json("{\"version\":2}").err().unwrap().starts_with("missing field `settings`"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use bevy::{ecs::{entity::Entity, resource::Resource}, math::{IVec2, Vec2, Vec3}};
+
+use crate::species::Species;
+
+/// What the grid remembers about a particle when it was inserted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridEntry{
+    pub entity: Entity,
+    pub species: Species,
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+/// A uniform grid of square cells, each listing the particles inside of it.
+/// Neighbour queries only look at the cells overlapping the query radius instead of every particle.
+#[derive(Resource)]
+pub struct SpatialGrid{
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<GridEntry>>,
+}
+
+impl SpatialGrid{
+    /// Queries with a radius of at most `cell_size` only have to look at 3x3 cells
+    pub fn new(cell_size: f32) -> Self{
+        Self { cell_size, cells: HashMap::new() }
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2{
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    /// Removes all particles. Cells that are still in use keep their memory for the next rebuild.
+    pub fn clear(&mut self){
+        self.cells.retain(|_, entries|{
+            let used = !entries.is_empty();
+            entries.clear();
+            used
+        });
+    }
+
+    pub fn insert(&mut self, entry: GridEntry){
+        let cell = self.cell(entry.position.truncate());
+        self.cells.entry(cell).or_default().push(entry);
+    }
+
+    /// All particles in cells overlapping the square around `position`. This includes some
+    /// particles further away than `radius`, so callers still have to check the distance.
+    /// The order only depends on the positions and the insertion order.
+    pub fn neighbours(&self, position: Vec2, radius: f32) -> impl Iterator<Item = &GridEntry>{
+        let min = self.cell(position - radius);
+        let max = self.cell(position + radius);
+
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use bevy::{ecs::entity::Entity, math::{Vec2, Vec3}};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{spatial::{GridEntry, SpatialGrid}, species::Species};
+
+    /// `count` particles spread over a square, so that every particle has about the same
+    /// number of neighbours no matter how many there are
+    fn random_entries(count: usize, seed: u64) -> Vec<GridEntry>{
+        let mut rng = StdRng::seed_from_u64(seed);
+        let size = (count as f32).sqrt() * 20.0;
+        (0..count).map(|i| GridEntry{
+            entity: Entity::from_raw(i as u32),
+            species: Species::Red,
+            position: Vec3::new(rng.random_range(-size..size), rng.random_range(-size..size), 1.0),
+            velocity: Vec3::ZERO,
+        }).collect()
+    }
+
+    fn grid(entries: &[GridEntry]) -> SpatialGrid{
+        let mut grid = SpatialGrid::new(75.0);
+        for entry in entries{
+            grid.insert(*entry);
+        }
+        grid
+    }
+
+    #[test]
+    fn finds_the_same_neighbours_as_a_full_scan(){
+        let entries = random_entries(2000, 1);
+        let grid = grid(&entries);
+
+        for entry in entries.iter().step_by(10){
+            let in_range = |other: &&GridEntry| entry.position.distance(other.position) <= 75.0;
+
+            let mut expected = entries.iter().filter(in_range).map(|e| e.entity).collect::<Vec<_>>();
+            let mut found = grid.neighbours(entry.position.truncate(), 75.0).filter(in_range).map(|e| e.entity).collect::<Vec<_>>();
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn clearing_keeps_used_cells(){
+        let mut grid = grid(&random_entries(100, 2));
+        grid.clear();
+
+        assert!(grid.cells.values().all(Vec::is_empty));
+        assert_eq!(grid.neighbours(Vec2::ZERO, 1000.0).count(), 0);
+
+        // Cells that stayed empty during a whole rebuild are dropped
+        grid.clear();
+        assert!(grid.cells.is_empty());
+    }
+
+    /// A full scan looks at every particle for every particle. The grid only looks at the ones
+    /// in nearby cells, which stay just as many when there are more particles at the same density.
+    #[test]
+    fn neighbour_queries_scale_with_density(){
+        let candidates_per_query = |count: usize|{
+            let entries = random_entries(count, 3);
+            let grid = grid(&entries);
+            let candidates = entries.iter().map(|entry| grid.neighbours(entry.position.truncate(), 75.0).count()).sum::<usize>();
+            candidates as f32 / count as f32
+        };
+
+        let few = candidates_per_query(1_000);
+        let many = candidates_per_query(16_000);
+        assert!(many < few * 1.25, "{few} candidates per query with 1000 particles, {many} with 16000");
+        // Far less than the 16000 a full scan looks at
+        assert!(many < 100.0);
+    }
+}
\ No newline at end of file
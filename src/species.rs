@@ -0,0 +1,138 @@
+use bevy::ecs::{component::Component, resource::Resource};
+#[cfg(feature = "gui")]
+use bevy::color::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::FlockParams;
+
+/// The kind of a particle. Particles flock with their own kind and react to the
+/// other kinds as the `SpeciesRules` say.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Species{
+    Red,
+    Green,
+    /// Hunts the other species
+    Predator,
+}
+
+impl Species{
+    pub const ALL: [Species; 3] = [Species::Red, Species::Green, Species::Predator];
+
+    pub fn index(self) -> usize{
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str{
+        match self{
+            Species::Red => "red",
+            Species::Green => "green",
+            Species::Predator => "predator",
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn color(self) -> Color{
+        match self{
+            Species::Red => Color::srgb(255.0, 0.0, 0.0),
+            Species::Green => Color::srgb(0.0, 255.0, 0.0),
+            Species::Predator => Color::srgb(255.0, 255.0, 255.0),
+        }
+    }
+
+    /// The flocking parameters of this species, based on the ones the script sets.
+    /// Greens are a bit faster than reds, so that they can catch up with the reds they chase.
+    /// Predators are faster than both.
+    pub fn flock_params(self, base: FlockParams) -> FlockParams{
+        let speed_up = match self{
+            Species::Red => return base,
+            Species::Green => 1.25,
+            Species::Predator => 1.5,
+        };
+        FlockParams{
+            acceleration: base.acceleration * speed_up,
+            max_speed: base.max_speed * speed_up,
+            ..base
+        }
+    }
+
+    /// How far the species that sees the furthest sees with the `base` parameters
+    pub fn max_perception_radius(base: FlockParams) -> f32{
+        Self::ALL.iter().map(|species| species.flock_params(base).perception_radius).fold(0.0, f32::max)
+    }
+}
+
+/// What a particle does about a neighbour
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reaction{
+    /// Moves with the neighbour like with its own kind
+    Flock,
+    /// Steers away from the neighbour
+    Avoid,
+    /// Steers towards the neighbour
+    Chase,
+    /// Escapes from the neighbour with the flee force instead of the steering rule
+    Flee,
+    /// Goes after the nearest of these neighbours and captures it when close enough
+    Hunt,
+}
+
+/// How every species reacts to every other species.
+/// Neighbours closer than the separation radius are avoided, unless they are hunted.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct SpeciesRules{
+    /// Indexed by the species of the particle, then by the species of its neighbour
+    reactions: [[Reaction; Species::ALL.len()]; Species::ALL.len()],
+}
+
+impl SpeciesRules{
+    pub fn reaction(&self, species: Species, neighbour: Species) -> Reaction{
+        self.reactions[species.index()][neighbour.index()]
+    }
+
+    pub fn set(&mut self, species: Species, neighbour: Species, reaction: Reaction){
+        self.reactions[species.index()][neighbour.index()] = reaction;
+    }
+}
+
+impl Default for SpeciesRules{
+    /// Both species keep to themselves, the greens chase the reds and the reds avoid them.
+    /// Predators hunt both and both flee from them.
+    fn default() -> Self {
+        let mut rules = Self { reactions: [[Reaction::Flock; Species::ALL.len()]; Species::ALL.len()] };
+        rules.set(Species::Red, Species::Green, Reaction::Avoid);
+        rules.set(Species::Green, Species::Red, Reaction::Chase);
+        for prey in [Species::Red, Species::Green]{
+            rules.set(Species::Predator, prey, Reaction::Hunt);
+            rules.set(prey, Species::Predator, Reaction::Flee);
+        }
+        rules
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use crate::{species::{Reaction, Species, SpeciesRules}, FlockParams};
+
+    #[test]
+    fn default_rules(){
+        let rules = SpeciesRules::default();
+        for species in Species::ALL{
+            assert_eq!(rules.reaction(species, species), Reaction::Flock);
+        }
+        assert_eq!(rules.reaction(Species::Red, Species::Green), Reaction::Avoid);
+        assert_eq!(rules.reaction(Species::Green, Species::Red), Reaction::Chase);
+        assert_eq!(rules.reaction(Species::Predator, Species::Green), Reaction::Hunt);
+        assert_eq!(rules.reaction(Species::Red, Species::Predator), Reaction::Flee);
+    }
+
+    #[test]
+    fn species_params_follow_the_script(){
+        let base = FlockParams{ max_speed: 50.0, cohesion: 0.5, ..Default::default() };
+        assert_eq!(Species::Red.flock_params(base), base);
+
+        let green = Species::Green.flock_params(base);
+        assert_eq!(green.max_speed, 62.5);
+        assert_eq!(green.cohesion, 0.5);
+    }
+}
\ No newline at end of file
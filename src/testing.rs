@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+use bevy::{app::App, MinimalPlugins};
+
+use crate::{add_simulation, cli::{SimulationSettings, DEFAULT_WINDOW_SIZE}, script::ParticleScript};
+
+/// An app without a window that runs the default script with `settings`
+pub fn test_app(settings: SimulationSettings) -> App{
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins);
+    add_simulation(&mut app, ParticleScript::default(), settings, DEFAULT_WINDOW_SIZE).unwrap();
+    app
+}
+
+/// A path in the temporary directory that no other test run uses at the same time.
+/// Tests of one run have to pick different names.
+pub fn temp_path(name: &str) -> PathBuf{
+    std::env::temp_dir().join(format!("{}_{name}", std::process::id()))
+}
\ No newline at end of file
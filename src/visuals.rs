@@ -0,0 +1,52 @@
+use bevy::{asset::{Assets, Handle}, color::Color, core_pipeline::core_2d::Camera2d, ecs::{entity::Entity, query::{Added, Changed}, resource::Resource, system::{Commands, Query, Res, ResMut}}, math::primitives::Circle, render::mesh::{Mesh, Mesh2d}, sprite::{ColorMaterial, MeshMaterial2d}};
+
+use crate::{obstacles::Obstacle, species::Species};
+
+/// The meshes and materials the world is drawn with. Only the windowed mode has them,
+/// the simulation itself works without.
+#[derive(Resource)]
+pub struct Visuals{
+    particle_mesh: Handle<Mesh>,
+    /// One per species, so that converted particles can change their color
+    species_materials: [Handle<ColorMaterial>; Species::ALL.len()],
+    obstacle_material: Handle<ColorMaterial>,
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+){
+    commands.spawn(Camera2d);
+    commands.insert_resource(Visuals{
+        particle_mesh: meshes.add(Circle::default()),
+        species_materials: Species::ALL.map(|species| materials.add(species.color())),
+        obstacle_material: materials.add(Color::srgb(0.4, 0.4, 0.4)),
+    });
+}
+
+/// Gives new particles, and particles that changed their species, the look of their species
+pub fn draw_particles(
+    mut commands: Commands,
+    visuals: Res<Visuals>,
+    particles: Query<(Entity, &Species), Changed<Species>>,
+){
+    for (entity, species) in &particles{
+        commands.entity(entity).insert((
+            Mesh2d(visuals.particle_mesh.clone()),
+            MeshMaterial2d(visuals.species_materials[species.index()].clone()),
+        ));
+    }
+}
+
+pub fn draw_obstacles(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    visuals: Res<Visuals>,
+    obstacles: Query<(Entity, &Obstacle), Added<Obstacle>>,
+){
+    for (entity, obstacle) in &obstacles{
+        let (mesh, transform) = obstacle.mesh();
+        commands.entity(entity).insert((Mesh2d(meshes.add(mesh)), MeshMaterial2d(visuals.obstacle_material.clone()), transform));
+    }
+}
\ No newline at end of file